@@ -0,0 +1,31 @@
+use advent_code_2020::day8::{debug, parse_program, Computer};
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: day8_debugger <program>");
+            process::exit(2);
+        }
+    };
+
+    let source = fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("could not read {}: {}", path, err);
+        process::exit(1);
+    });
+
+    let program = parse_program(&source).unwrap_or_else(|(line, err)| {
+        eprintln!("{}:{}: {}", path, line, err);
+        process::exit(1);
+    });
+
+    let mut computer = Computer::new(program);
+    computer.set_tracing(true);
+
+    let stdin = io::stdin();
+    debug(&mut computer, stdin.lock(), io::stdout()).unwrap();
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Congruence {
+    pub residue: i128,
+    pub modulus: i128,
+}
+
+impl Congruence {
+    pub fn new(residue: i128, modulus: i128) -> Self {
+        Congruence { residue, modulus }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CrtError {
+    Empty,
+    InvalidModulus(i128),
+    NoSolution { index: usize },
+    Overflow,
+}
+
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrtError::Empty => write!(f, "no congruences to solve"),
+            CrtError::InvalidModulus(modulus) => write!(f, "invalid modulus {}", modulus),
+            CrtError::NoSolution { index } => {
+                write!(f, "congruence {} conflicts with the ones before it", index)
+            }
+            CrtError::Overflow => write!(f, "combined modulus does not fit in 128 bits"),
+        }
+    }
+}
+
+impl std::error::Error for CrtError {}
+
+// Returns (g, x, y) such that a * x + b * y == g == gcd(a, b)
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_s, mut s) = (1, 0);
+    let (mut old_t, mut t) = (0, 1);
+
+    while r != 0 {
+        let quotient = old_r / r;
+        let next_r = old_r - quotient * r;
+        old_r = r;
+        r = next_r;
+        let next_s = old_s - quotient * s;
+        old_s = s;
+        s = next_s;
+        let next_t = old_t - quotient * t;
+        old_t = t;
+        t = next_t;
+    }
+
+    if old_r < 0 {
+        (-old_r, -old_s, -old_t)
+    } else {
+        (old_r, old_s, old_t)
+    }
+}
+
+pub fn gcd(a: i128, b: i128) -> i128 {
+    extended_gcd(a, b).0
+}
+
+pub fn mod_inverse(value: i128, modulus: i128) -> Option<i128> {
+    let (g, x, _) = extended_gcd(value.rem_euclid(modulus), modulus);
+    if g == 1 {
+        Some(x.rem_euclid(modulus))
+    } else {
+        None
+    }
+}
+
+pub fn mul_mod(a: i128, b: i128, modulus: i128) -> i128 {
+    let a = a.rem_euclid(modulus);
+    let mut b = b.rem_euclid(modulus);
+    if let Some(product) = a.checked_mul(b) {
+        return product % modulus;
+    }
+
+    // Fall back to double-and-add when the product would overflow
+    let mut result = 0;
+    let mut base = a;
+    while b > 0 {
+        if b & 1 == 1 {
+            result = add_mod(result, base, modulus);
+        }
+        base = add_mod(base, base, modulus);
+        b >>= 1;
+    }
+    result
+}
+
+fn add_mod(a: i128, b: i128, modulus: i128) -> i128 {
+    // Both values are already reduced, so comparing against the gap avoids overflowing a + b
+    if a >= modulus - b {
+        a - (modulus - b)
+    } else {
+        a + b
+    }
+}
+
+fn combine(first: Congruence, second: Congruence, index: usize) -> Result<Congruence, CrtError> {
+    let (g, _, _) = extended_gcd(first.modulus, second.modulus);
+    let difference = second.residue - first.residue;
+    if difference % g != 0 {
+        return Err(CrtError::NoSolution { index });
+    }
+
+    let reduced = second.modulus / g;
+    let lcm = (first.modulus / g)
+        .checked_mul(second.modulus)
+        .ok_or(CrtError::Overflow)?;
+    let inverse = mod_inverse(first.modulus / g, reduced).unwrap();
+    let k = mul_mod(difference / g, inverse, reduced);
+    let residue = add_mod(first.residue, mul_mod(first.modulus, k, lcm), lcm);
+
+    Ok(Congruence::new(residue, lcm))
+}
+
+// Finds the smallest non-negative x satisfying every congruence, along with the
+// modulus of the combined solution. Moduli do not need to be pairwise coprime.
+pub fn solve(congruences: &[Congruence]) -> Result<Congruence, CrtError> {
+    let mut result: Option<Congruence> = None;
+
+    for (index, congruence) in congruences.iter().enumerate() {
+        if congruence.modulus <= 0 {
+            return Err(CrtError::InvalidModulus(congruence.modulus));
+        }
+        let congruence = Congruence::new(
+            congruence.residue.rem_euclid(congruence.modulus),
+            congruence.modulus,
+        );
+
+        result = Some(match result {
+            None => congruence,
+            Some(current) => combine(current, congruence, index)?,
+        });
+    }
+
+    result.ok_or(CrtError::Empty)
+}
@@ -1,6 +1,7 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashMap;
-use std::iter::once;
+use num_bigint::BigUint;
+use std::collections::BTreeMap;
+use std::fmt;
 
 #[aoc_generator(day10)]
 pub fn parser(input: &str) -> Vec<u32> {
@@ -11,49 +12,97 @@ pub fn parser(input: &str) -> Vec<u32> {
     adapters
 }
 
-#[aoc(day10, part1)]
-pub fn solve_part1(input: &[u32]) -> u32 {
-    let device = *input.last().unwrap() + 3;
-    let (diff_1, diff_3, _) =
-        input
-            .iter()
-            .chain(once(&device))
-            .fold((0, 0, 0), |(diff_1, diff_3, curr), value| {
-                match value - curr {
-                    1 => (diff_1 + 1, diff_3, *value),
-                    3 => (diff_1, diff_3 + 1, *value),
-                    _ => (diff_1, diff_3, *value),
-                }
-            });
+#[derive(Debug, PartialEq)]
+pub enum ChainError {
+    Gap { from: u32, to: u32 },
+}
 
-    diff_1 * diff_3
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::Gap { from, to } => {
+                write!(
+                    f,
+                    "no adapter bridges the gap from {} to {} jolts",
+                    from, to
+                )
+            }
+        }
+    }
 }
 
-#[aoc(day10, part2)]
-pub fn solve_part2(input: &[u32]) -> u64 {
-    let mut cache = HashMap::new();
-    count_possibilities(input, 0, &mut cache)
+impl std::error::Error for ChainError {}
+
+pub struct ChainAnalysis {
+    pub differences: BTreeMap<u32, usize>,
+    pub arrangements: BigUint,
+    pub mandatory: Vec<u32>,
+    pub removable: Vec<u32>,
 }
 
-fn count_possibilities(adapters: &[u32], current: usize, cache: &mut HashMap<usize, u64>) -> u64 {
-    if current == adapters.len() - 1 {
-        return 1;
+impl ChainAnalysis {
+    pub fn difference_count(&self, difference: u32) -> usize {
+        self.differences.get(&difference).copied().unwrap_or(0)
     }
+}
 
-    if let Some(value) = cache.get(&current) {
-        return *value;
-    }
+// Expects the adapters with the outlet (0) included, as returned by `parser`. The device
+// is rated `device_offset` jolts above the highest adapter.
+pub fn analyze(
+    adapters: &[u32],
+    max_step: u32,
+    device_offset: u32,
+) -> Result<ChainAnalysis, ChainError> {
+    let mut chain = adapters.to_vec();
+    chain.sort_unstable();
+    chain.push(chain.last().copied().unwrap_or(0) + device_offset);
 
-    let mut possibilities = 0;
+    let mut differences = BTreeMap::new();
+    for pair in chain.windows(2) {
+        let difference = pair[1] - pair[0];
+        if difference > max_step {
+            return Err(ChainError::Gap {
+                from: pair[0],
+                to: pair[1],
+            });
+        }
+        *differences.entry(difference).or_insert(0) += 1;
+    }
 
-    for index in (current + 1)..adapters.len() {
-        if adapters[index] > adapters[current] + 3 {
-            break;
+    // ways[i] is the number of arrangements that reach chain[i] from the outlet
+    let mut ways: Vec<BigUint> = Vec::with_capacity(chain.len());
+    ways.push(BigUint::from(1u32));
+    let mut first = 0;
+    for (index, &joltage) in chain.iter().enumerate().skip(1) {
+        while joltage - chain[first] > max_step {
+            first += 1;
         }
-        possibilities += count_possibilities(adapters, index, cache);
+        let total = ways[first..index]
+            .iter()
+            .fold(BigUint::from(0u32), |acc, n| acc + n);
+        ways.push(total);
     }
 
-    cache.insert(current, possibilities);
+    // An adapter can only be skipped if its neighbours are close enough to connect directly
+    let (mandatory, removable) = (1..chain.len() - 1)
+        .map(|index| (chain[index], chain[index + 1] - chain[index - 1] > max_step))
+        .partition::<Vec<_>, _>(|&(_, required)| required);
+
+    Ok(ChainAnalysis {
+        differences,
+        arrangements: ways.pop().unwrap(),
+        mandatory: mandatory.into_iter().map(|(joltage, _)| joltage).collect(),
+        removable: removable.into_iter().map(|(joltage, _)| joltage).collect(),
+    })
+}
 
-    possibilities
+#[aoc(day10, part1)]
+pub fn solve_part1(input: &[u32]) -> usize {
+    let analysis = analyze(input, 3, 3).unwrap();
+    analysis.difference_count(1) * analysis.difference_count(3)
+}
+
+#[aoc(day10, part2)]
+pub fn solve_part2(input: &[u32]) -> BigUint {
+    analyze(input, 3, 3).unwrap().arrangements
 }
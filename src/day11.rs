@@ -1,5 +1,12 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::iter::{from_fn, once};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 #[derive(PartialEq, Copy, Clone)]
 enum Spot {
@@ -18,14 +25,19 @@ impl fmt::Display for Spot {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 pub struct Lobby {
-    layout: Vec<Vec<Spot>>,
+    width: usize,
+    spots: Vec<Spot>,
 }
 
 impl fmt::Display for Lobby {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for row in &self.layout {
+        if self.width == 0 {
+            return Ok(());
+        }
+
+        for row in self.spots.chunks(self.width) {
             for value in row {
                 write!(f, "{}", value)?;
             }
@@ -35,180 +47,346 @@ impl fmt::Display for Lobby {
     }
 }
 
-impl Lobby {
-    fn count_surrounding(&self, row: usize, col: usize) -> u8 {
-        let lrow = row.saturating_sub(1);
-        let urow = if row + 1 >= self.layout.len() {
-            row
-        } else {
-            row + 1
-        };
-        let lcol = col.saturating_sub(1);
-        let ucol = if col + 1 >= self.layout[0].len() {
-            col
-        } else {
-            col + 1
-        };
-
-        let mut matching = 0;
-        for r in lrow..=urow {
-            for c in lcol..=ucol {
-                if self.layout[r][c] == Spot::Occupied && !(r == row && c == col) {
-                    matching += 1;
-                }
-            }
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Neighborhood {
+    Adjacent,
+    LineOfSight,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SeatingRules {
+    pub neighborhood: Neighborhood,
+    pub tolerance: usize,
+    pub max_distance: Option<usize>,
+}
+
+impl SeatingRules {
+    pub fn adjacent() -> Self {
+        SeatingRules {
+            neighborhood: Neighborhood::Adjacent,
+            tolerance: 4,
+            max_distance: None,
         }
+    }
 
-        matching
+    pub fn line_of_sight() -> Self {
+        SeatingRules {
+            neighborhood: Neighborhood::LineOfSight,
+            tolerance: 5,
+            max_distance: None,
+        }
     }
 
-    fn count_visible(&self, row: usize, col: usize) -> u8 {
-        self.visible_direction(row, col, (-1, -1))
-            + self.visible_direction(row, col, (-1, 0))
-            + self.visible_direction(row, col, (-1, 1))
-            + self.visible_direction(row, col, (0, -1))
-            + self.visible_direction(row, col, (0, 1))
-            + self.visible_direction(row, col, (1, -1))
-            + self.visible_direction(row, col, (1, 0))
-            + self.visible_direction(row, col, (1, 1))
+    fn reach(&self) -> usize {
+        match self.neighborhood {
+            Neighborhood::Adjacent => 1,
+            Neighborhood::LineOfSight => self.max_distance.unwrap_or(usize::MAX),
+        }
     }
+}
 
-    fn visible_direction(&self, row: usize, col: usize, direction: (i8, i8)) -> u8 {
-        let mut row = row as i8;
-        let mut col = col as i8;
+impl Lobby {
+    fn height(&self) -> usize {
+        self.spots.len().checked_div(self.width).unwrap_or(0)
+    }
 
-        let max_row = self.layout.len() as i8;
-        let max_col = self.layout[0].len() as i8;
+    fn count_occupied(&self) -> usize {
+        self.spots
+            .iter()
+            .filter(|spot| **spot == Spot::Occupied)
+            .count()
+    }
 
-        loop {
+    fn visible_seat(&self, index: usize, direction: (isize, isize), reach: usize) -> Option<usize> {
+        let mut row = (index / self.width) as isize;
+        let mut col = (index % self.width) as isize;
+
+        for _ in 0..reach {
             row += direction.0;
             col += direction.1;
 
-            if row < 0 || col < 0 || row >= max_row || col >= max_col {
-                return 0;
+            if row < 0 || col < 0 || row >= self.height() as isize || col >= self.width as isize {
+                return None;
             }
 
-            match self.layout[row as usize][col as usize] {
-                Spot::Occupied => return 1,
-                Spot::Empty => return 0,
-                Spot::Floor => {}
+            let target = row as usize * self.width + col as usize;
+            if self.spots[target] != Spot::Floor {
+                return Some(target);
             }
         }
-    }
 
-    fn count_occupied(&self) -> usize {
-        self.layout
-            .iter()
-            .map(|row| row.iter().filter(|col| **col == Spot::Occupied).count())
-            .sum()
+        None
     }
 
-    fn step(&self) -> Lobby {
-        let layout = self
-            .layout
-            .iter()
-            .enumerate()
-            .map(|(row, data)| {
-                data.iter()
-                    .enumerate()
-                    .map(|(col, spot)| match spot {
-                        Spot::Floor => Spot::Floor,
-                        Spot::Empty => {
-                            if self.count_surrounding(row, col) == 0 {
-                                Spot::Occupied
-                            } else {
-                                Spot::Empty
-                            }
-                        }
-                        Spot::Occupied => {
-                            if self.count_surrounding(row, col) >= 4 {
-                                Spot::Empty
-                            } else {
-                                Spot::Occupied
-                            }
-                        }
-                    })
-                    .collect()
+    fn neighbors(&self, rules: &SeatingRules) -> Vec<Vec<usize>> {
+        (0..self.spots.len())
+            .map(|index| match self.spots[index] {
+                Spot::Floor => Vec::new(),
+                _ => DIRECTIONS
+                    .iter()
+                    .filter_map(|&direction| self.visible_seat(index, direction, rules.reach()))
+                    .collect(),
             })
+            .collect()
+    }
+}
+
+struct Simulation {
+    width: usize,
+    current: Vec<Spot>,
+    next: Vec<Spot>,
+    neighbors: Vec<Vec<usize>>,
+    tolerance: usize,
+    pending: Vec<usize>,
+    queued: Vec<bool>,
+}
+
+impl Simulation {
+    fn new(lobby: &Lobby, rules: &SeatingRules) -> Self {
+        let pending: Vec<_> = (0..lobby.spots.len())
+            .filter(|&index| lobby.spots[index] != Spot::Floor)
             .collect();
 
-        Lobby { layout }
+        Simulation {
+            width: lobby.width,
+            current: lobby.spots.clone(),
+            next: lobby.spots.clone(),
+            neighbors: lobby.neighbors(rules),
+            tolerance: rules.tolerance,
+            pending,
+            queued: vec![false; lobby.spots.len()],
+        }
     }
 
-    fn step_part2(&self) -> Lobby {
-        let layout = self
-            .layout
+    fn occupied_neighbors(&self, index: usize) -> usize {
+        self.neighbors[index]
             .iter()
-            .enumerate()
-            .map(|(row, data)| {
-                data.iter()
-                    .enumerate()
-                    .map(|(col, spot)| match spot {
-                        Spot::Floor => Spot::Floor,
-                        Spot::Empty => {
-                            if self.count_visible(row, col) == 0 {
-                                Spot::Occupied
-                            } else {
-                                Spot::Empty
-                            }
-                        }
-                        Spot::Occupied => {
-                            if self.count_visible(row, col) >= 5 {
-                                Spot::Empty
-                            } else {
-                                Spot::Occupied
-                            }
-                        }
-                    })
-                    .collect()
+            .filter(|&&neighbor| self.current[neighbor] == Spot::Occupied)
+            .count()
+    }
+
+    // Returns false once no seat changed
+    fn step(&mut self) -> bool {
+        let mut changed = Vec::new();
+
+        for &index in &self.pending {
+            let updated = match self.current[index] {
+                Spot::Empty if self.occupied_neighbors(index) == 0 => Spot::Occupied,
+                Spot::Occupied if self.occupied_neighbors(index) >= self.tolerance => Spot::Empty,
+                spot => spot,
+            };
+
+            if updated != self.current[index] {
+                self.next[index] = updated;
+                changed.push(index);
+            }
+        }
+
+        std::mem::swap(&mut self.current, &mut self.next);
+
+        // Only a changed seat and its neighbours can change in the following generation
+        self.pending.clear();
+        for &index in &changed {
+            self.next[index] = self.current[index];
+            for &seat in once(&index).chain(&self.neighbors[index]) {
+                if !self.queued[seat] {
+                    self.queued[seat] = true;
+                    self.pending.push(seat);
+                }
+            }
+        }
+        for &index in &self.pending {
+            self.queued[index] = false;
+        }
+
+        !changed.is_empty()
+    }
+
+    fn lobby(&self) -> Lobby {
+        Lobby {
+            width: self.width,
+            spots: self.current.clone(),
+        }
+    }
+
+    fn fingerprint(&self) -> Vec<u64> {
+        let mut bits = vec![0; self.current.len().div_ceil(64)];
+        for (index, spot) in self.current.iter().enumerate() {
+            if *spot == Spot::Occupied {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+        bits
+    }
+}
+
+pub enum Outcome {
+    Stable { steps: usize, lobby: Lobby },
+    Cycle { start: usize, period: usize },
+    StepLimit { steps: usize, lobby: Lobby },
+}
+
+pub fn simulate(lobby: &Lobby, rules: &SeatingRules, max_steps: usize) -> Outcome {
+    let mut simulation = Simulation::new(lobby, rules);
+    let mut seen = HashMap::new();
+    seen.insert(simulation.fingerprint(), 0);
+
+    for steps in 0..max_steps {
+        if !simulation.step() {
+            return Outcome::Stable {
+                steps,
+                lobby: simulation.lobby(),
+            };
+        }
+
+        if let Some(start) = seen.insert(simulation.fingerprint(), steps + 1) {
+            return Outcome::Cycle {
+                start,
+                period: steps + 1 - start,
+            };
+        }
+    }
+
+    Outcome::StepLimit {
+        steps: max_steps,
+        lobby: simulation.lobby(),
+    }
+}
+
+pub fn generations(lobby: &Lobby, rules: &SeatingRules) -> impl Iterator<Item = Lobby> {
+    let mut simulation = Simulation::new(lobby, rules);
+    let mut seen = HashSet::new();
+    let mut finished = false;
+    seen.insert(simulation.fingerprint());
+
+    // Stops once the lobby settles or returns to an earlier layout
+    once(lobby.clone()).chain(from_fn(move || {
+        if finished || !simulation.step() || !seen.insert(simulation.fingerprint()) {
+            finished = true;
+            None
+        } else {
+            Some(simulation.lobby())
+        }
+    }))
+}
+
+pub fn play_ansi<W: Write>(
+    lobby: &Lobby,
+    rules: &SeatingRules,
+    mut writer: W,
+    delay: Duration,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for (generation, frame) in generations(lobby, rules).enumerate() {
+        write!(writer, "\x1b[H\x1b[2J{}", frame)?;
+        writeln!(
+            writer,
+            "generation {}, {} occupied",
+            generation,
+            frame.count_occupied()
+        )?;
+        writer.flush()?;
+        thread::sleep(delay);
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub fn write_ppm_frames<P: AsRef<Path>>(
+    lobby: &Lobby,
+    rules: &SeatingRules,
+    directory: P,
+    scale: usize,
+) -> io::Result<usize> {
+    fs::create_dir_all(&directory)?;
+
+    let mut count = 0;
+    for (generation, frame) in generations(lobby, rules).enumerate() {
+        let path = directory
+            .as_ref()
+            .join(format!("frame_{:05}.ppm", generation));
+        write_ppm(&frame, BufWriter::new(File::create(path)?), scale)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn write_ppm<W: Write>(lobby: &Lobby, mut writer: W, scale: usize) -> io::Result<()> {
+    let rendered = lobby.to_string();
+    let rows: Vec<_> = rendered.lines().collect();
+    writeln!(
+        writer,
+        "P6\n{} {}\n255",
+        lobby.width * scale,
+        rows.len() * scale
+    )?;
+
+    for row in rows {
+        let pixels: Vec<u8> = row
+            .chars()
+            .flat_map(|chr| {
+                let color: [u8; 3] = match chr {
+                    'L' => [60, 160, 60],
+                    '#' => [200, 50, 50],
+                    _ => [40, 40, 40],
+                };
+                color.repeat(scale)
             })
             .collect();
-
-        Lobby { layout }
+        for _ in 0..scale {
+            writer.write_all(&pixels)?;
+        }
     }
+
+    writer.flush()
 }
 
 #[aoc_generator(day11)]
 pub fn parser(input: &str) -> Lobby {
-    let layout = input
+    let width = input.lines().next().map_or(0, str::len);
+    let spots = input
         .lines()
-        .map(|line| {
-            line.chars()
-                .map(|chr| match chr {
-                    'L' => Spot::Empty,
-                    '#' => Spot::Occupied,
-                    '.' => Spot::Floor,
-                    _ => unreachable!(),
-                })
-                .collect()
+        .flat_map(|line| {
+            line.chars().map(|chr| match chr {
+                'L' => Spot::Empty,
+                '#' => Spot::Occupied,
+                '.' => Spot::Floor,
+                _ => unreachable!(),
+            })
         })
         .collect();
 
-    Lobby { layout }
+    Lobby { width, spots }
+}
+
+fn settle(lobby: &Lobby, rules: &SeatingRules) -> usize {
+    match simulate(lobby, rules, 10_000) {
+        Outcome::Stable { lobby, .. } => lobby.count_occupied(),
+        Outcome::Cycle { start, period } => panic!(
+            "Lobby cycles with period {} from generation {}",
+            period, start
+        ),
+        Outcome::StepLimit { steps, .. } => panic!("Lobby did not settle in {} steps", steps),
+    }
 }
 
 #[aoc(day11, part1)]
 pub fn solve_part1(input: &Lobby) -> usize {
-    let mut current = input.step();
-
-    loop {
-        let next_step = current.step();
-        if current == next_step {
-            return current.count_occupied();
-        }
-        current = next_step;
-    }
+    settle(input, &SeatingRules::adjacent())
 }
 
 #[aoc(day11, part2)]
 pub fn solve_part2(input: &Lobby) -> usize {
-    let mut current = input.step_part2();
-
-    loop {
-        let next_step = current.step_part2();
-        if current == next_step {
-            return current.count_occupied();
-        }
-        current = next_step;
-    }
+    settle(input, &SeatingRules::line_of_sight())
 }
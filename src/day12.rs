@@ -1,6 +1,8 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::fmt;
+use std::io::{self, Write};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Action {
     North(i32),
     South(i32),
@@ -31,152 +33,333 @@ pub fn parser(input: &str) -> Vec<Action> {
         .collect()
 }
 
-struct Ship {
-    position: (i32, i32),
-    heading: (i32, i32),
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mode {
+    Heading,
+    Waypoint,
 }
 
-impl Ship {
-    fn new() -> Self {
-        Ship {
-            position: (0, 0),
-            heading: (1, 0),
+impl Mode {
+    fn initial_vector(&self) -> (i32, i32) {
+        match self {
+            Mode::Heading => (1, 0),
+            Mode::Waypoint => (10, 1),
         }
     }
+}
 
-    fn act(&mut self, action: Action) {
-        match action {
-            Action::North(amount) => {
-                self.position = (self.position.0, self.position.1 + amount);
-            }
-            Action::South(amount) => {
-                self.position = (self.position.0, self.position.1 - amount);
-            }
-            Action::East(amount) => {
-                self.position = (self.position.0 + amount, self.position.1);
-            }
-            Action::West(amount) => {
-                self.position = (self.position.0 - amount, self.position.1);
-            }
-            Action::Left(amount) => match amount {
-                90 => {
-                    self.heading = (-self.heading.1, self.heading.0);
-                }
-                180 => {
-                    self.heading = (-self.heading.0, -self.heading.1);
-                }
-                270 => {
-                    self.heading = (self.heading.1, -self.heading.0);
-                }
-                _ => {}
-            },
-            Action::Right(amount) => match amount {
-                90 => {
-                    self.heading = (self.heading.1, -self.heading.0);
-                }
-                180 => {
-                    self.heading = (-self.heading.0, -self.heading.1);
-                }
-                270 => {
-                    self.heading = (-self.heading.1, self.heading.0);
-                }
-                _ => {}
-            },
-            Action::Forward(amount) => {
-                let dx = self.heading.0 * amount;
-                let dy = self.heading.1 * amount;
-                self.position = (self.position.0 + dx, self.position.1 + dy);
+#[derive(Debug, PartialEq)]
+pub enum NavigationError {
+    InvalidAngle(i32),
+}
+
+impl fmt::Display for NavigationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NavigationError::InvalidAngle(angle) => {
+                write!(f, "cannot turn {} degrees, only multiples of 90", angle)
             }
         }
     }
+}
 
-    fn distance(&self) -> i32 {
-        self.position.0.abs() + self.position.1.abs()
+impl std::error::Error for NavigationError {}
+
+fn rotate_left(vector: (i32, i32), degrees: i32) -> (i32, i32) {
+    match degrees.rem_euclid(360) / 90 {
+        0 => vector,
+        1 => (-vector.1, vector.0),
+        2 => (-vector.0, -vector.1),
+        _ => (vector.1, -vector.0),
     }
 }
 
-#[aoc(day12, part1)]
-pub fn solve_part1(input: &[Action]) -> i32 {
-    input
-        .iter()
-        .fold(Ship::new(), |mut ship, &action| {
-            ship.act(action);
-            ship
-        })
-        .distance()
+// The ship's state after an action, with `vector` holding its heading or waypoint
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Step {
+    pub action: Action,
+    pub position: (i32, i32),
+    pub vector: (i32, i32),
 }
 
-struct ShipWaypoint {
+pub struct Navigator {
+    mode: Mode,
     position: (i32, i32),
-    waypoint: (i32, i32),
+    vector: (i32, i32),
+    trajectory: Vec<(i32, i32)>,
+    log: Vec<Step>,
 }
 
-impl ShipWaypoint {
-    fn new() -> Self {
-        Self {
+impl Navigator {
+    pub fn new(mode: Mode) -> Self {
+        Navigator {
+            mode,
             position: (0, 0),
-            waypoint: (10, 1),
+            vector: mode.initial_vector(),
+            trajectory: vec![(0, 0)],
+            log: Vec::new(),
         }
     }
 
-    fn act(&mut self, action: Action) {
-        match action {
-            Action::North(amount) => {
-                self.waypoint = (self.waypoint.0, self.waypoint.1 + amount);
-            }
-            Action::South(amount) => {
-                self.waypoint = (self.waypoint.0, self.waypoint.1 - amount);
+    pub fn act(&mut self, action: Action) -> Result<(), NavigationError> {
+        let shift = match action {
+            Action::Left(amount) | Action::Right(amount) if amount % 90 != 0 => {
+                return Err(NavigationError::InvalidAngle(amount));
             }
-            Action::East(amount) => {
-                self.waypoint = (self.waypoint.0 + amount, self.waypoint.1);
+            Action::North(amount) => Some((0, amount)),
+            Action::South(amount) => Some((0, -amount)),
+            Action::East(amount) => Some((amount, 0)),
+            Action::West(amount) => Some((-amount, 0)),
+            Action::Left(amount) => {
+                self.vector = rotate_left(self.vector, amount);
+                None
             }
-            Action::West(amount) => {
-                self.waypoint = (self.waypoint.0 - amount, self.waypoint.1);
+            Action::Right(amount) => {
+                self.vector = rotate_left(self.vector, -amount);
+                None
             }
-            Action::Left(amount) => match amount {
-                90 => {
-                    self.waypoint = (-self.waypoint.1, self.waypoint.0);
-                }
-                180 => {
-                    self.waypoint = (-self.waypoint.0, -self.waypoint.1);
-                }
-                270 => {
-                    self.waypoint = (self.waypoint.1, -self.waypoint.0);
-                }
-                _ => {}
-            },
-            Action::Right(amount) => match amount {
-                90 => {
-                    self.waypoint = (self.waypoint.1, -self.waypoint.0);
-                }
-                180 => {
-                    self.waypoint = (-self.waypoint.0, -self.waypoint.1);
-                }
-                270 => {
-                    self.waypoint = (-self.waypoint.1, self.waypoint.0);
-                }
-                _ => {}
-            },
             Action::Forward(amount) => {
-                let dx = self.waypoint.0 * amount;
-                let dy = self.waypoint.1 * amount;
+                let dx = self.vector.0 * amount;
+                let dy = self.vector.1 * amount;
                 self.position = (self.position.0 + dx, self.position.1 + dy);
+                None
+            }
+        };
+
+        // Cardinal moves steer the ship directly, or nudge the waypoint it follows
+        if let Some((dx, dy)) = shift {
+            match self.mode {
+                Mode::Heading => self.position = (self.position.0 + dx, self.position.1 + dy),
+                Mode::Waypoint => self.vector = (self.vector.0 + dx, self.vector.1 + dy),
             }
         }
+
+        if self.trajectory.last() != Some(&self.position) {
+            self.trajectory.push(self.position);
+        }
+        self.log.push(Step {
+            action,
+            position: self.position,
+            vector: self.vector,
+        });
+        Ok(())
     }
 
-    fn distance(&self) -> i32 {
+    pub fn navigate<'a, I>(mut self, actions: I) -> Result<Self, NavigationError>
+    where
+        I: IntoIterator<Item = &'a Action>,
+    {
+        for &action in actions {
+            self.act(action)?;
+        }
+        Ok(self)
+    }
+
+    pub fn position(&self) -> (i32, i32) {
+        self.position
+    }
+
+    // Every distinct position the ship moved through, starting at the origin
+    pub fn trajectory(&self) -> &[(i32, i32)] {
+        &self.trajectory
+    }
+
+    pub fn log(&self) -> &[Step] {
+        &self.log
+    }
+
+    pub fn distance(&self) -> i32 {
         self.position.0.abs() + self.position.1.abs()
     }
+
+    pub fn route(&self) -> Route<'_> {
+        Route {
+            points: &self.trajectory,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+impl Bounds {
+    pub fn width(&self) -> i32 {
+        self.max.0 - self.min.0
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max.1 - self.min.1
+    }
+}
+
+pub struct Route<'a> {
+    points: &'a [(i32, i32)],
+}
+
+impl<'a> Route<'a> {
+    pub fn bounds(&self) -> Bounds {
+        self.points.iter().fold(
+            Bounds {
+                min: self.points[0],
+                max: self.points[0],
+            },
+            |bounds, &(x, y)| Bounds {
+                min: (bounds.min.0.min(x), bounds.min.1.min(y)),
+                max: (bounds.max.0.max(x), bounds.max.1.max(y)),
+            },
+        )
+    }
+
+    pub fn length(&self) -> f64 {
+        self.points
+            .windows(2)
+            .map(|pair| {
+                let dx = (pair[1].0 - pair[0].0) as f64;
+                let dy = (pair[1].1 - pair[0].1) as f64;
+                dx.hypot(dy)
+            })
+            .sum()
+    }
+
+    pub fn write_svg<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let bounds = self.bounds();
+        let margin = (bounds.width().max(bounds.height()) / 20).max(1);
+        let stroke = margin as f64 / 4.0;
+        let start = self.points[0];
+        let end = self.points[self.points.len() - 1];
+
+        // SVG grows downwards, so y is negated to keep north at the top
+        writeln!(
+            writer,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+            bounds.min.0 - margin,
+            -bounds.max.1 - margin,
+            bounds.width() + 2 * margin,
+            bounds.height() + 2 * margin
+        )?;
+        writeln!(
+            writer,
+            "  <title>path length {:.1}, bounds ({}, {}) to ({}, {})</title>",
+            self.length(),
+            bounds.min.0,
+            bounds.min.1,
+            bounds.max.0,
+            bounds.max.1
+        )?;
+        writeln!(
+            writer,
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"gray\" stroke-width=\"{}\" stroke-dasharray=\"{}\"/>",
+            bounds.min.0,
+            -bounds.max.1,
+            bounds.width(),
+            bounds.height(),
+            stroke / 2.0,
+            stroke * 2.0
+        )?;
+
+        let points: Vec<_> = self
+            .points
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, -y))
+            .collect();
+        writeln!(
+            writer,
+            "  <polyline points=\"{}\" fill=\"none\" stroke=\"navy\" stroke-width=\"{}\"/>",
+            points.join(" "),
+            stroke
+        )?;
+        writeln!(
+            writer,
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"green\"><title>start</title></circle>",
+            start.0,
+            -start.1,
+            stroke * 2.0
+        )?;
+        writeln!(
+            writer,
+            "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"red\"><title>end</title></circle>",
+            end.0,
+            -end.1,
+            stroke * 2.0
+        )?;
+        writeln!(writer, "</svg>")
+    }
+
+    pub fn ascii(&self, columns: usize, rows: usize) -> String {
+        let bounds = self.bounds();
+        let columns = columns.max(2);
+        let rows = rows.max(2);
+        let scale = |value: f64, min: i32, span: i32, cells: usize| -> usize {
+            if span == 0 {
+                0
+            } else {
+                ((value - min as f64) / span as f64 * (cells - 1) as f64).round() as usize
+            }
+        };
+        let cell = |(x, y): (f64, f64)| {
+            let column = scale(x, bounds.min.0, bounds.width(), columns);
+            let row = scale(y, bounds.min.1, bounds.height(), rows);
+            (column, rows - 1 - row)
+        };
+
+        let mut grid = vec![vec![' '; columns]; rows];
+        let samples = 2 * (columns + rows);
+        for pair in self.points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            for step in 0..=samples {
+                let t = step as f64 / samples as f64;
+                let point = (
+                    from.0 as f64 + (to.0 - from.0) as f64 * t,
+                    from.1 as f64 + (to.1 - from.1) as f64 * t,
+                );
+                let (column, row) = cell(point);
+                grid[row][column] = '*';
+            }
+        }
+
+        let start = self.points[0];
+        let end = self.points[self.points.len() - 1];
+        let (column, row) = cell((start.0 as f64, start.1 as f64));
+        grid[row][column] = 'S';
+        let (column, row) = cell((end.0 as f64, end.1 as f64));
+        grid[row][column] = 'E';
+
+        let border = format!("+{}+\n", "-".repeat(columns));
+        let mut plot = border.clone();
+        for row in grid {
+            plot.push('|');
+            plot.extend(row);
+            plot.push_str("|\n");
+        }
+        plot.push_str(&border);
+        plot.push_str(&format!(
+            "x {}..{}, y {}..{}, path length {:.1}\n",
+            bounds.min.0,
+            bounds.max.0,
+            bounds.min.1,
+            bounds.max.1,
+            self.length()
+        ));
+
+        plot
+    }
+}
+
+#[aoc(day12, part1)]
+pub fn solve_part1(input: &[Action]) -> i32 {
+    Navigator::new(Mode::Heading)
+        .navigate(input)
+        .unwrap()
+        .distance()
 }
 
 #[aoc(day12, part2)]
 pub fn solve_part2(input: &[Action]) -> i32 {
-    input
-        .iter()
-        .fold(ShipWaypoint::new(), |mut ship, &action| {
-            ship.act(action);
-            ship
-        })
+    Navigator::new(Mode::Waypoint)
+        .navigate(input)
+        .unwrap()
         .distance()
 }
@@ -1,64 +1,187 @@
+use crate::crt::{self, Congruence, CrtError};
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
 
-#[aoc_generator(day13, part1)]
-pub fn parser(input: &str) -> (u32, Vec<u32>) {
-    let mut parts = input.lines();
-    let timestamp = parts.next().unwrap().parse().unwrap();
-    let busses = parts
-        .next()
-        .unwrap()
-        .split(',')
-        .filter_map(|b| b.parse().ok())
-        .collect();
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bus {
+    pub id: u64,
+    pub offset: usize,
+}
 
-    (timestamp, busses)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    pub timestamp: u64,
+    pub busses: Vec<Bus>,
 }
 
-#[aoc(day13, part1)]
-pub fn solve_part1(input: &(u32, Vec<u32>)) -> u32 {
-    let mut min = u32::MAX;
-    let mut id = 0;
-    let timestamp = input.0;
-
-    for &value in &input.1 {
-        let wait = value - (timestamp % value);
-        if wait < min {
-            min = wait;
-            id = value;
+#[derive(Debug, PartialEq)]
+pub enum ScheduleError {
+    MissingTimestamp,
+    InvalidTimestamp(String),
+    MissingBusses,
+    InvalidBus { offset: usize, entry: String },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::MissingTimestamp => write!(f, "schedule has no timestamp"),
+            ScheduleError::InvalidTimestamp(value) => write!(f, "invalid timestamp '{}'", value),
+            ScheduleError::MissingBusses => write!(f, "schedule has no bus list"),
+            ScheduleError::InvalidBus { offset, entry } => {
+                write!(f, "invalid bus '{}' at offset {}", entry, offset)
+            }
         }
     }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut lines = input.lines().map(str::trim).filter(|l| !l.is_empty());
+        let timestamp = lines.next().ok_or(ScheduleError::MissingTimestamp)?;
+        let timestamp = timestamp
+            .parse()
+            .map_err(|_| ScheduleError::InvalidTimestamp(timestamp.into()))?;
 
-    min * id
+        let busses = lines
+            .next()
+            .ok_or(ScheduleError::MissingBusses)?
+            .split(',')
+            .map(str::trim)
+            .enumerate()
+            .filter(|&(_, entry)| entry != "x")
+            .map(|(offset, entry)| match entry.parse() {
+                Ok(id) if id > 0 => Ok(Bus { id, offset }),
+                _ => Err(ScheduleError::InvalidBus {
+                    offset,
+                    entry: entry.into(),
+                }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Schedule { timestamp, busses })
+    }
 }
 
-#[aoc_generator(day13, part2)]
-pub fn parser_part2(input: &str) -> Vec<Option<u64>> {
-    input
-        .lines()
-        .nth(1)
-        .unwrap()
-        .split(',')
-        .map(|b| b.parse().ok())
-        .collect()
+#[aoc_generator(day13)]
+pub fn parser(input: &str) -> Schedule {
+    input.parse().unwrap()
+}
+
+#[aoc(day13, part1)]
+pub fn solve_part1(input: &Schedule) -> u64 {
+    let (id, departure) = next_departures(input, input.timestamp)
+        .into_iter()
+        .min_by_key(|&(_, departure)| departure)
+        .unwrap();
+
+    (departure - input.timestamp) * id
 }
 
 #[aoc(day13, part2)]
-pub fn solve_part2(input: &[Option<u64>]) -> u64 {
-    let mut timestamp = input[0].unwrap();
-    let mut multiple = timestamp;
-
-    for (index, &bus) in input.iter().enumerate().skip(1) {
-        let index = index as u64;
-        if let Some(id) = bus {
-            loop {
-                if (timestamp + index) % id == 0 {
-                    break;
-                }
-                timestamp += multiple;
+pub fn solve_part2(input: &Schedule) -> i128 {
+    let congruences: Vec<_> = input
+        .busses
+        .iter()
+        .map(|bus| Congruence::new(-(bus.offset as i128), bus.id as i128))
+        .collect();
+
+    crt::solve(&congruences).unwrap().residue
+}
+
+pub fn next_departures(schedule: &Schedule, from: u64) -> Vec<(u64, u64)> {
+    schedule
+        .busses
+        .iter()
+        .map(|bus| (bus.id, from.div_ceil(bus.id) * bus.id))
+        .collect()
+}
+
+pub fn aligned_departures(
+    first: u64,
+    second: u64,
+    offset: u64,
+    window: Range<u64>,
+) -> Result<Vec<u64>, CrtError> {
+    let alignment = match crt::solve(&[
+        Congruence::new(0, first as i128),
+        Congruence::new(-(offset as i128), second as i128),
+    ]) {
+        Ok(alignment) => alignment,
+        Err(CrtError::NoSolution { .. }) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let period = alignment.modulus;
+    let start = window.start as i128;
+    let first_time = start + (alignment.residue - start).rem_euclid(period);
+
+    Ok((0..)
+        .map(|n| first_time + n * period)
+        .take_while(|&time| time < window.end as i128)
+        .map(|time| time as u64)
+        .collect())
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Alignment {
+    pub time: i128,
+    pub busses: Vec<u64>,
+}
+
+// Tries every group of `count` busses, which is fine for the handful of busses in a schedule
+pub fn earliest_alignment(schedule: &Schedule, count: usize) -> Option<Alignment> {
+    let busses = &schedule.busses;
+    if count == 0 || count > busses.len() {
+        return None;
+    }
+
+    let mut best: Option<Alignment> = None;
+    let mut chosen = Vec::with_capacity(count);
+    visit_groups(busses, count, 0, &mut chosen, &mut |group| {
+        let congruences: Vec<_> = group
+            .iter()
+            .map(|bus| Congruence::new(-(bus.offset as i128), bus.id as i128))
+            .collect();
+
+        if let Ok(solution) = crt::solve(&congruences) {
+            let earlier = match &best {
+                Some(current) => solution.residue < current.time,
+                None => true,
+            };
+            if earlier {
+                best = Some(Alignment {
+                    time: solution.residue,
+                    busses: group.iter().map(|bus| bus.id).collect(),
+                });
             }
-            multiple *= id;
         }
+    });
+
+    best
+}
+
+fn visit_groups<T: Copy, F: FnMut(&[T])>(
+    items: &[T],
+    size: usize,
+    start: usize,
+    chosen: &mut Vec<T>,
+    visit: &mut F,
+) {
+    if chosen.len() == size {
+        visit(chosen);
+        return;
     }
 
-    timestamp
+    for index in start..items.len() {
+        chosen.push(items[index]);
+        visit_groups(items, size, index + 1, chosen, visit);
+        chosen.pop();
+    }
 }
@@ -97,77 +97,136 @@ pub fn solve_part1(input: &str) -> u64 {
     memory.values().sum()
 }
 
-struct BitMask2 {
-    masks: Vec<(u64, u64)>,
+struct FloatingMask {
+    ones: u64,
+    floating: u64,
 }
 
-impl BitMask2 {
+impl<'a> From<&'a str> for FloatingMask {
+    fn from(value: &'a str) -> Self {
+        let mut ones = 0;
+        let mut floating = 0;
+
+        for chr in value.chars() {
+            ones <<= 1;
+            floating <<= 1;
+            match chr {
+                '0' => {}
+                '1' => ones |= 1,
+                'X' => floating |= 1,
+                _ => unreachable!(),
+            }
+        }
+
+        Self { ones, floating }
+    }
+}
+
+impl FloatingMask {
     fn new() -> Self {
-        Self { masks: Vec::new() }
+        Self {
+            ones: 0,
+            floating: 0,
+        }
     }
 
-    fn apply(&self, value: u64) -> impl Iterator<Item = u64> + '_ {
-        self.masks
-            .iter()
-            .map(move |(and_mask, or_mask)| (value & and_mask) | or_mask)
+    fn apply(&self, address: u64) -> AddressPattern {
+        AddressPattern {
+            fixed: (address | self.ones) & !self.floating,
+            floating: self.floating,
+        }
     }
 }
 
-impl<'a> From<&'a str> for BitMask2 {
-    fn from(value: &'a str) -> Self {
-        let mut masks = vec![(0, 0)];
+// A set of addresses: bits in `floating` take either value, the rest match `fixed`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AddressPattern {
+    fixed: u64,
+    floating: u64,
+}
 
-        for chr in value.chars() {
-            for value_mut in masks.iter_mut() {
-                value_mut.0 <<= 1;
-                value_mut.1 <<= 1;
-            }
+impl AddressPattern {
+    pub fn count(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
 
-            match chr {
-                '0' => {
-                    for value_mut in masks.iter_mut() {
-                        value_mut.0 |= 1;
-                    }
-                }
-                '1' => {
-                    for value_mut in masks.iter_mut() {
-                        value_mut.1 |= 1;
-                        value_mut.0 |= 1;
-                    }
-                }
-                'X' => {
-                    let mut new_masks = Vec::with_capacity(masks.len() * 2);
+    pub fn intersection(&self, other: &AddressPattern) -> Option<AddressPattern> {
+        let both_fixed = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & both_fixed != 0 {
+            return None;
+        }
 
-                    for value in masks.iter() {
-                        new_masks.push(*value);
-                        new_masks.push((value.0 | 1, value.1 | 1));
-                    }
+        Some(AddressPattern {
+            fixed: self.fixed | other.fixed,
+            floating: self.floating & other.floating,
+        })
+    }
 
-                    masks = new_masks;
-                }
-                _ => unreachable!(),
-            }
+    // Splits the addresses in self that are not in other into disjoint patterns
+    pub fn difference(&self, other: &AddressPattern) -> Vec<AddressPattern> {
+        if self.intersection(other).is_none() {
+            return vec![*self];
+        }
+
+        let mut pieces = Vec::new();
+        let mut remaining = *self;
+        let mut split = self.floating & !other.floating;
+
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= !bit;
+
+            remaining.floating &= !bit;
+            pieces.push(AddressPattern {
+                fixed: (remaining.fixed & !bit) | (!other.fixed & bit),
+                floating: remaining.floating,
+            });
+            remaining.fixed = (remaining.fixed & !bit) | (other.fixed & bit);
         }
 
-        Self { masks }
+        pieces
+    }
+}
+
+#[derive(Default)]
+pub struct FloatingMemory {
+    regions: Vec<(AddressPattern, u64)>,
+}
+
+impl FloatingMemory {
+    pub fn write(&mut self, pattern: AddressPattern, value: u64) {
+        self.regions = self
+            .regions
+            .iter()
+            .flat_map(|&(region, stored)| {
+                region
+                    .difference(&pattern)
+                    .into_iter()
+                    .map(move |piece| (piece, stored))
+            })
+            .collect();
+        self.regions.push((pattern, value));
+    }
+
+    pub fn sum(&self) -> u128 {
+        self.regions
+            .iter()
+            .map(|(pattern, value)| pattern.count() * *value as u128)
+            .sum()
     }
 }
 
 #[aoc(day14, part2)]
-fn solve_part2(input: &str) -> u64 {
-    let mut mask = BitMask2::new();
-    let mut memory = HashMap::new();
+pub fn solve_part2(input: &str) -> u128 {
+    let mut mask = FloatingMask::new();
+    let mut memory = FloatingMemory::default();
 
     for cmd in input.lines().map(Command::from) {
         match cmd {
             Command::Mask(new_mask) => mask = new_mask.into(),
-            Command::Assign(location, value) => {
-                for actual in mask.apply(location) {
-                    memory.insert(actual, value);
-                }
-            }
+            Command::Assign(location, value) => memory.write(mask.apply(location), value),
         }
     }
 
-    memory.values().sum()
+    memory.sum()
 }
@@ -1,186 +1,605 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use nom::branch::alt;
+use nom::bytes::complete::{take_till1, take_while, take_while1};
+use nom::character::complete::char;
+use nom::combinator::{map, verify};
+use nom::multi::{separated_list0, separated_list1};
+use nom::sequence::{delimited, separated_pair};
+use nom::IResult;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::ops::RangeInclusive;
+use std::path::Path;
 
-pub struct Passport {
-    birth_year: Option<u16>,
-    issue_year: Option<u16>,
-    expiration_year: Option<u16>,
-    height: Option<String>,
-    hair_color: Option<String>,
-    eye_color: Option<String>,
-    passport_id: Option<String>,
-    country_id: Option<String>,
+const DEFAULT_SCHEMA: &str = "\
+byr required year 1920-2002
+iyr required year 2010-2020
+eyr required year 2020-2030
+hgt required height cm:150-193 in:59-76
+hcl required color
+ecl required enum amb blu brn gry grn hzl oth
+pid required digits 9
+cid optional any
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldKind {
+    Year(RangeInclusive<u16>),
+    Height(Vec<(String, RangeInclusive<u16>)>),
+    HexColor,
+    Enum(Vec<String>),
+    Digits(usize),
+    Any,
 }
 
-impl Passport {
-    fn new() -> Self {
-        Passport {
-            birth_year: None,
-            issue_year: None,
-            expiration_year: None,
-            height: None,
-            hair_color: None,
-            eye_color: None,
-            passport_id: None,
-            country_id: None,
+impl FieldKind {
+    fn check(&self, value: &str) -> Result<(), Violation> {
+        match self {
+            FieldKind::Year(range) => {
+                let year: u16 = value.parse().map_err(|_| Violation::Malformed)?;
+                if range.contains(&year) {
+                    Ok(())
+                } else {
+                    Err(Violation::OutOfRange)
+                }
+            }
+            FieldKind::Height(units) => {
+                let split = value
+                    .find(|c: char| !c.is_ascii_digit())
+                    .ok_or(Violation::Malformed)?;
+                let (amount, unit) = value.split_at(split);
+                let amount: u16 = amount.parse().map_err(|_| Violation::Malformed)?;
+                let (_, range) = units
+                    .iter()
+                    .find(|(name, _)| name == unit)
+                    .ok_or(Violation::UnknownUnit)?;
+                if range.contains(&amount) {
+                    Ok(())
+                } else {
+                    Err(Violation::OutOfRange)
+                }
+            }
+            FieldKind::HexColor => match value.strip_prefix('#') {
+                Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(()),
+                _ => Err(Violation::Malformed),
+            },
+            FieldKind::Enum(options) => {
+                if options.iter().any(|option| option == value) {
+                    Ok(())
+                } else {
+                    Err(Violation::NotAllowed)
+                }
+            }
+            FieldKind::Digits(len) => {
+                if value.len() == *len && value.chars().all(|c| c.is_ascii_digit()) {
+                    Ok(())
+                } else {
+                    Err(Violation::Malformed)
+                }
+            }
+            FieldKind::Any => Ok(()),
         }
     }
+}
 
-    fn is_valid_primitive(&self) -> bool {
-        self.birth_year.is_some()
-            && self.issue_year.is_some()
-            && self.expiration_year.is_some()
-            && self.height.is_some()
-            && self.hair_color.is_some()
-            && self.eye_color.is_some()
-            && self.passport_id.is_some()
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldKind::Year(range) => write!(f, "year {}-{}", range.start(), range.end()),
+            FieldKind::Height(units) => {
+                f.write_str("height")?;
+                for (unit, range) in units {
+                    write!(f, " {}:{}-{}", unit, range.start(), range.end())?;
+                }
+                Ok(())
+            }
+            FieldKind::HexColor => f.write_str("color"),
+            FieldKind::Enum(options) => write!(f, "enum {}", options.join(" ")),
+            FieldKind::Digits(len) => write!(f, "digits {}", len),
+            FieldKind::Any => f.write_str("any"),
+        }
     }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldRule {
+    pub key: String,
+    pub required: bool,
+    pub kind: FieldKind,
+}
 
-    fn is_valid_advanced(&self) -> bool {
-        self.valid_birth_year()
-            && self.valid_issue_year()
-            && self.valid_expiration_year()
-            && self.valid_height()
-            && self.valid_hair_color()
-            && self.valid_eye_color()
-            && self.valid_passport_id()
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Violation {
+    Missing,
+    Malformed,
+    OutOfRange,
+    UnknownUnit,
+    NotAllowed,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Violation::Missing => "missing",
+            Violation::Malformed => "malformed",
+            Violation::OutOfRange => "out of range",
+            Violation::UnknownUnit => "unknown unit",
+            Violation::NotAllowed => "not an allowed value",
+        })
     }
+}
 
-    fn birth_year(&mut self, year: u16) {
-        self.birth_year = Some(year);
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub key: String,
+    pub value: Option<String>,
+    pub violation: Violation,
+    pub rule: FieldKind,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(
+                f,
+                "{}:{} is {} (expected {})",
+                self.key, value, self.violation, self.rule
+            ),
+            None => write!(f, "{} is {}", self.key, self.violation),
+        }
     }
+}
 
-    fn valid_birth_year(&self) -> bool {
-        match self.birth_year {
-            Some(year) => 1920 <= year && year <= 2002,
-            None => false,
+#[derive(Debug)]
+pub enum SchemaError {
+    Io(io::Error),
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Io(err) => write!(f, "could not read schema: {}", err),
+            SchemaError::Invalid { line, message } => {
+                write!(f, "invalid schema on line {}: {}", line, message)
+            }
         }
     }
+}
 
-    fn issue_year(&mut self, year: u16) {
-        self.issue_year = Some(year);
+impl std::error::Error for SchemaError {}
+
+impl From<io::Error> for SchemaError {
+    fn from(err: io::Error) -> Self {
+        SchemaError::Io(err)
+    }
+}
+
+pub struct Schema {
+    rules: Vec<FieldRule>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Schema::parse(DEFAULT_SCHEMA).unwrap()
+    }
+}
+
+impl Schema {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SchemaError> {
+        Schema::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(input: &str) -> Result<Self, SchemaError> {
+        let rules = input
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(index, line)| {
+                parse_rule(line).map_err(|message| SchemaError::Invalid {
+                    line: index + 1,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Schema { rules })
+    }
+
+    pub fn rules(&self) -> &[FieldRule] {
+        &self.rules
+    }
+
+    pub fn missing_fields(&self, passport: &Passport) -> Vec<FieldError> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.required && passport.get(&rule.key).is_none())
+            .map(|rule| FieldError {
+                key: rule.key.clone(),
+                value: None,
+                violation: Violation::Missing,
+                rule: rule.kind.clone(),
+            })
+            .collect()
+    }
+
+    pub fn validate(&self, passport: &Passport) -> Vec<FieldError> {
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                let value = passport.get(&rule.key);
+                let result = match value {
+                    Some(value) => rule.kind.check(value),
+                    None if rule.required => Err(Violation::Missing),
+                    None => Ok(()),
+                };
+
+                result.err().map(|violation| FieldError {
+                    key: rule.key.clone(),
+                    value: value.map(String::from),
+                    violation,
+                    rule: rule.kind.clone(),
+                })
+            })
+            .collect()
     }
 
-    fn valid_issue_year(&self) -> bool {
-        match self.issue_year {
-            Some(year) => 2010 <= year && year <= 2020,
-            None => false,
+    pub fn report(&self, record: usize, passport: &Passport) -> ValidationReport {
+        ValidationReport {
+            record,
+            errors: self.validate(passport),
         }
     }
+}
+
+fn parse_rule(line: &str) -> Result<FieldRule, String> {
+    let mut parts = line.split_whitespace();
+    let key = parts.next().ok_or("missing field key")?.to_string();
+    let required = match parts.next() {
+        Some("required") => true,
+        Some("optional") => false,
+        Some(other) => return Err(format!("expected required or optional, found {}", other)),
+        None => return Err("missing required flag".into()),
+    };
 
-    fn expiration_year(&mut self, year: u16) {
-        self.expiration_year = Some(year);
+    let kind = match parts.next() {
+        Some("year") => FieldKind::Year(parse_range(parts.next().ok_or("missing year range")?)?),
+        Some("height") => FieldKind::Height(
+            parts
+                .by_ref()
+                .map(|unit| {
+                    let (name, range) = unit
+                        .split_once(':')
+                        .ok_or_else(|| format!("expected unit:range, found {}", unit))?;
+                    Ok((name.to_string(), parse_range(range)?))
+                })
+                .collect::<Result<_, String>>()?,
+        ),
+        Some("color") => FieldKind::HexColor,
+        Some("enum") => FieldKind::Enum(parts.by_ref().map(String::from).collect()),
+        Some("digits") => FieldKind::Digits(
+            parts
+                .next()
+                .and_then(|len| len.parse().ok())
+                .ok_or("missing digit count")?,
+        ),
+        Some("any") => FieldKind::Any,
+        Some(other) => return Err(format!("unknown field type {}", other)),
+        None => return Err("missing field type".into()),
+    };
+
+    match parts.next() {
+        Some(extra) => Err(format!("unexpected trailing value {}", extra)),
+        None => Ok(FieldRule {
+            key,
+            required,
+            kind,
+        }),
     }
+}
+
+fn parse_range(range: &str) -> Result<RangeInclusive<u16>, String> {
+    let bounds = range
+        .split_once('-')
+        .and_then(|(low, high)| Some((low.parse().ok()?, high.parse().ok()?)));
+
+    match bounds {
+        Some((low, high)) => Ok(low..=high),
+        None => Err(format!("expected range low-high, found {}", range)),
+    }
+}
+
+pub struct Passport {
+    fields: HashMap<String, String>,
+}
 
-    fn valid_expiration_year(&self) -> bool {
-        match self.expiration_year {
-            Some(year) => 2020 <= year && year <= 2030,
-            None => false,
+impl Passport {
+    fn new() -> Self {
+        Passport {
+            fields: HashMap::new(),
         }
     }
 
-    fn height(&mut self, height: String) {
-        self.height = Some(height);
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
     }
+}
 
-    fn valid_height(&self) -> bool {
-        match self.height.as_ref() {
-            Some(height) => {
-                if let Some(h) = height.strip_suffix("cm").and_then(|v| v.parse::<u8>().ok()) {
-                    return 150 <= h && h <= 193;
-                }
+enum Field<'a> {
+    Pair(&'a str, &'a str),
+    Malformed(&'a str),
+}
 
-                if let Some(h) = height.strip_suffix("in").and_then(|v| v.parse::<u8>().ok()) {
-                    return 59 <= h && h <= 76;
-                }
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    DuplicateKey { record: usize, key: String },
+    UnknownKey { record: usize, key: String },
+    MalformedField { record: usize, token: String },
+}
 
-                false
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::DuplicateKey { record, key } => {
+                write!(f, "record {}: duplicate key {}", record, key)
+            }
+            Diagnostic::UnknownKey { record, key } => {
+                write!(f, "record {}: unknown key {}", record, key)
+            }
+            Diagnostic::MalformedField { record, token } => {
+                write!(f, "record {}: malformed field {}", record, token)
             }
-            None => false,
         }
     }
+}
+
+pub struct Batch {
+    pub passports: Vec<Passport>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+pub fn parse_batch(input: &str, schema: &Schema) -> Batch {
+    // Every non-whitespace run parses, at worst as a malformed field, so this cannot fail
+    let (_, records) = delimited(
+        whitespace,
+        separated_list0(record_separator, record),
+        whitespace,
+    )(input)
+    .expect("batch parser accepts any input");
 
-    fn hair_color(&mut self, color: String) {
-        self.hair_color = Some(color);
+    let mut diagnostics = Vec::new();
+    let passports = records
+        .into_iter()
+        .enumerate()
+        .map(|(index, fields)| {
+            let mut passport = Passport::new();
+            for field in fields {
+                match field {
+                    Field::Pair(key, value) => {
+                        if !schema.rules.iter().any(|rule| rule.key == key) {
+                            diagnostics.push(Diagnostic::UnknownKey {
+                                record: index,
+                                key: key.into(),
+                            });
+                        }
+                        if passport.fields.contains_key(key) {
+                            diagnostics.push(Diagnostic::DuplicateKey {
+                                record: index,
+                                key: key.into(),
+                            });
+                        } else {
+                            passport.fields.insert(key.into(), value.into());
+                        }
+                    }
+                    Field::Malformed(token) => {
+                        diagnostics.push(Diagnostic::MalformedField {
+                            record: index,
+                            token: token.into(),
+                        });
+                    }
+                }
+            }
+            passport
+        })
+        .collect();
+
+    Batch {
+        passports,
+        diagnostics,
     }
+}
+
+fn token(input: &str) -> IResult<&str, &str> {
+    take_till1(char::is_whitespace)(input)
+}
+
+fn field(input: &str) -> IResult<&str, Field<'_>> {
+    alt((
+        map(
+            separated_pair(
+                take_till1(|c: char| c == ':' || c.is_whitespace()),
+                char(':'),
+                token,
+            ),
+            |(key, value)| Field::Pair(key, value),
+        ),
+        map(token, Field::Malformed),
+    ))(input)
+}
+
+fn whitespace(input: &str) -> IResult<&str, &str> {
+    take_while(char::is_whitespace)(input)
+}
 
-    fn valid_hair_color(&self) -> bool {
-        self.hair_color
-            .as_ref()
-            .and_then(|color| color.strip_prefix('#'))
-            .map(|color| color.chars().all(|c| c.is_digit(16)))
-            .unwrap_or(false)
+// Records are separated by whitespace spanning a blank line, fields by any other whitespace
+fn field_separator(input: &str) -> IResult<&str, &str> {
+    verify(take_while1(char::is_whitespace), |gap: &str| {
+        gap.matches('\n').count() < 2
+    })(input)
+}
+
+fn record_separator(input: &str) -> IResult<&str, &str> {
+    verify(take_while1(char::is_whitespace), |gap: &str| {
+        gap.matches('\n').count() >= 2
+    })(input)
+}
+
+fn record(input: &str) -> IResult<&str, Vec<Field<'_>>> {
+    separated_list1(field_separator, field)(input)
+}
+
+pub struct ValidationReport {
+    pub record: usize,
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
     }
 
-    fn eye_color(&mut self, color: String) {
-        self.eye_color = Some(color);
+    pub fn missing(&self) -> impl Iterator<Item = &str> + '_ {
+        self.errors
+            .iter()
+            .filter(|err| err.violation == Violation::Missing)
+            .map(|err| err.key.as_str())
     }
 
-    fn valid_eye_color(&self) -> bool {
-        matches!(
-            self.eye_color.as_deref(),
-            Some("amb")
-                | Some("blu")
-                | Some("brn")
-                | Some("gry")
-                | Some("grn")
-                | Some("hzl")
-                | Some("oth")
-        )
+    pub fn invalid(&self) -> impl Iterator<Item = &FieldError> + '_ {
+        self.errors
+            .iter()
+            .filter(|err| err.violation != Violation::Missing)
     }
 
-    fn passport_id(&mut self, id: String) {
-        self.passport_id = Some(id);
+    fn failing_keys(&self) -> Vec<String> {
+        self.errors.iter().map(|err| err.key.clone()).collect()
     }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return write!(f, "record {}: valid", self.record);
+        }
 
-    fn valid_passport_id(&self) -> bool {
-        self.passport_id
-            .as_ref()
-            .map(|pid| pid.chars().all(|c| c.is_digit(10)) && pid.len() == 9)
-            .unwrap_or(false)
+        write!(f, "record {}:", self.record)?;
+        for err in &self.errors {
+            write!(f, "\n  {}", err)?;
+        }
+        Ok(())
     }
+}
 
-    fn country_id(&mut self, id: String) {
-        self.country_id = Some(id);
+pub fn validation_reports(passports: &[Passport], schema: &Schema) -> Vec<ValidationReport> {
+    passports
+        .iter()
+        .enumerate()
+        .map(|(record, passport)| schema.report(record, passport))
+        .collect()
+}
+
+pub struct ReportSummary {
+    pub total: usize,
+    pub valid: usize,
+    pub failures: BTreeMap<Vec<String>, usize>,
+}
+
+impl<'a> FromIterator<&'a ValidationReport> for ReportSummary {
+    fn from_iter<I: IntoIterator<Item = &'a ValidationReport>>(reports: I) -> Self {
+        reports.into_iter().fold(
+            ReportSummary {
+                total: 0,
+                valid: 0,
+                failures: BTreeMap::new(),
+            },
+            |mut summary, report| {
+                summary.total += 1;
+                if report.is_valid() {
+                    summary.valid += 1;
+                } else {
+                    *summary.failures.entry(report.failing_keys()).or_insert(0) += 1;
+                }
+                summary
+            },
+        )
     }
 }
 
-#[aoc_generator(day4)]
-pub fn parser(input: &str) -> Vec<Passport> {
-    let data = input.lines().flat_map(|l| l.split(' '));
-    let mut passport_list = Vec::new();
-    let mut passport = Passport::new();
-
-    for item in data {
-        if item.is_empty() {
-            let mut next = Passport::new();
-            std::mem::swap(&mut passport, &mut next);
-            passport_list.push(next);
-        } else {
-            let key = &item[0..3];
-            let value = &item[4..];
-            match key {
-                "byr" => passport.birth_year(value.parse().unwrap()),
-                "iyr" => passport.issue_year(value.parse().unwrap()),
-                "eyr" => passport.expiration_year(value.parse().unwrap()),
-                "hgt" => passport.height(value.into()),
-                "hcl" => passport.hair_color(value.into()),
-                "ecl" => passport.eye_color(value.into()),
-                "pid" => passport.passport_id(value.into()),
-                "cid" => passport.country_id(value.into()),
-                e => panic!("Unknown passport data: {}", e),
+impl fmt::Display for ReportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} of {} passports are valid", self.valid, self.total)?;
+
+        let mut failures: Vec<_> = self.failures.iter().collect();
+        failures.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (keys, count) in failures {
+            if keys.len() == 1 {
+                writeln!(f, "{} passports fail only on {}", count, keys[0])?;
+            } else {
+                writeln!(f, "{} passports fail on {}", count, keys.join(", "))?;
             }
         }
+        Ok(())
+    }
+}
+
+pub fn write_csv<'a, W, I>(mut writer: W, reports: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a ValidationReport>,
+{
+    writeln!(writer, "record,valid,key,value,violation,rule")?;
+
+    for report in reports {
+        if report.is_valid() {
+            writeln!(writer, "{},true,,,,", report.record)?;
+        }
+
+        for err in &report.errors {
+            writeln!(
+                writer,
+                "{},false,{},{},{},{}",
+                report.record,
+                csv_field(&err.key),
+                csv_field(err.value.as_deref().unwrap_or("")),
+                err.violation,
+                csv_field(&err.rule.to_string()),
+            )?;
+        }
     }
 
-    passport_list
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(&[',', '"'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[aoc_generator(day4)]
+pub fn parser(input: &str) -> Vec<Passport> {
+    parse_batch(input, &Schema::default()).passports
 }
 
 #[aoc(day4, part1)]
 pub fn solve_part1(input: &[Passport]) -> usize {
-    input.iter().filter(|p| p.is_valid_primitive()).count()
+    let schema = Schema::default();
+    input
+        .iter()
+        .filter(|p| schema.missing_fields(p).is_empty())
+        .count()
 }
 
 #[aoc(day4, part2)]
 pub fn solve_part2(input: &[Passport]) -> usize {
-    input.iter().filter(|p| p.is_valid_advanced()).count()
+    let schema = Schema::default();
+    input
+        .iter()
+        .filter(|p| schema.validate(p).is_empty())
+        .count()
 }
@@ -1,58 +1,262 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Seat {
-    row: u8,
-    column: u8,
+    pub row: u32,
+    pub column: u32,
 }
 
-impl Seat {
-    fn seat_id(&self) -> u32 {
-        (self.row as u32) * 8 + (self.column as u32)
+#[derive(Debug, PartialEq)]
+pub enum PassError {
+    WrongLength { expected: usize, found: usize },
+    InvalidCharacter { position: usize, found: char },
+    OutOfRange(Seat),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::WrongLength { expected, found } => write!(
+                f,
+                "boarding pass should be {} characters, found {}",
+                expected, found
+            ),
+            PassError::InvalidCharacter { position, found } => {
+                write!(f, "invalid character '{}' at position {}", found, position)
+            }
+            PassError::OutOfRange(seat) => write!(
+                f,
+                "seat at row {}, column {} is outside the plane",
+                seat.row, seat.column
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BoardingPassCodec {
+    row_bits: u32,
+    column_bits: u32,
+}
+
+impl Default for BoardingPassCodec {
+    fn default() -> Self {
+        BoardingPassCodec::new(7, 3)
     }
 }
 
-fn binary_fold(acc: (u8, u8), value: &u8) -> (u8, u8) {
-    let midpoint = acc.0 + ((acc.1 - acc.0) / 2);
-    match value {
-        b'F' | b'L' => (acc.0, midpoint),
-        b'B' | b'R' => (midpoint + 1, acc.1),
-        _ => unreachable!(),
+impl BoardingPassCodec {
+    pub fn new(row_bits: u32, column_bits: u32) -> Self {
+        assert!(
+            row_bits + column_bits < 32,
+            "boarding passes are limited to 31 characters"
+        );
+        BoardingPassCodec {
+            row_bits,
+            column_bits,
+        }
+    }
+
+    pub fn rows(&self) -> u32 {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> u32 {
+        1 << self.column_bits
+    }
+
+    fn len(&self) -> usize {
+        (self.row_bits + self.column_bits) as usize
+    }
+
+    pub fn contains(&self, seat: &Seat) -> bool {
+        seat.row < self.rows() && seat.column < self.columns()
+    }
+
+    pub fn seat_id(&self, seat: &Seat) -> u32 {
+        (seat.row << self.column_bits) | seat.column
+    }
+
+    pub fn seat(&self, seat_id: u32) -> Seat {
+        Seat {
+            row: seat_id >> self.column_bits,
+            column: seat_id & (self.columns() - 1),
+        }
+    }
+
+    pub fn decode(&self, pass: &str) -> Result<Seat, PassError> {
+        let found = pass.chars().count();
+        if found != self.len() {
+            return Err(PassError::WrongLength {
+                expected: self.len(),
+                found,
+            });
+        }
+
+        let seat_id = pass
+            .chars()
+            .enumerate()
+            .try_fold(0, |id, (position, chr)| {
+                let bit = match (position < self.row_bits as usize, chr) {
+                    (true, 'F') | (false, 'L') => 0,
+                    (true, 'B') | (false, 'R') => 1,
+                    (_, found) => return Err(PassError::InvalidCharacter { position, found }),
+                };
+                Ok((id << 1) | bit)
+            })?;
+
+        Ok(self.seat(seat_id))
+    }
+
+    pub fn encode(&self, seat: &Seat) -> Result<String, PassError> {
+        if !self.contains(seat) {
+            return Err(PassError::OutOfRange(*seat));
+        }
+
+        let rows = (0..self.row_bits)
+            .rev()
+            .map(|bit| if seat.row >> bit & 1 == 1 { 'B' } else { 'F' });
+        let columns = (0..self.column_bits).rev().map(|bit| {
+            if seat.column >> bit & 1 == 1 {
+                'R'
+            } else {
+                'L'
+            }
+        });
+
+        Ok(rows.chain(columns).collect())
     }
 }
 
-impl<'a> From<&'a [u8]> for Seat {
-    fn from(specifier: &'a [u8]) -> Seat {
-        let (row, _) = specifier.iter().take(7).fold((0, 127), binary_fold);
-        let (column, _) = specifier.iter().skip(7).take(3).fold((0, 7), binary_fold);
+pub struct SeatMap {
+    codec: BoardingPassCodec,
+    occupied: Vec<bool>,
+}
+
+impl SeatMap {
+    pub fn new(codec: BoardingPassCodec, seats: &[Seat]) -> Result<Self, PassError> {
+        let mut occupied = vec![false; (codec.rows() * codec.columns()) as usize];
+        for seat in seats {
+            if !codec.contains(seat) {
+                return Err(PassError::OutOfRange(*seat));
+            }
+            occupied[codec.seat_id(seat) as usize] = true;
+        }
+
+        Ok(SeatMap { codec, occupied })
+    }
+
+    pub fn is_occupied(&self, seat: &Seat) -> bool {
+        self.codec.contains(seat) && self.occupied[self.codec.seat_id(seat) as usize]
+    }
+
+    pub fn empty_seats(&self) -> Vec<Seat> {
+        self.occupied
+            .iter()
+            .enumerate()
+            .filter(|(_, &occupied)| !occupied)
+            .map(|(id, _)| self.codec.seat(id as u32))
+            .collect()
+    }
+
+    pub fn gaps(&self) -> Vec<Range<u32>> {
+        let mut gaps = Vec::new();
+        let mut start = None;
+
+        for (id, &occupied) in self.occupied.iter().enumerate() {
+            let id = id as u32;
+            match (occupied, start) {
+                (false, None) => start = Some(id),
+                (true, Some(first)) => {
+                    gaps.push(first..id);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(first) = start {
+            gaps.push(first..self.occupied.len() as u32);
+        }
+
+        gaps
+    }
+
+    fn row_is_empty(&self, row: u32) -> bool {
+        let columns = self.codec.columns();
+        let start = (row * columns) as usize;
+        self.occupied[start..start + columns as usize]
+            .iter()
+            .all(|&occupied| !occupied)
+    }
+
+    pub fn missing_front_rows(&self) -> Range<u32> {
+        let end = (0..self.codec.rows())
+            .find(|&row| !self.row_is_empty(row))
+            .unwrap_or_else(|| self.codec.rows());
+        0..end
+    }
+
+    pub fn missing_back_rows(&self) -> Range<u32> {
+        let start = (0..self.codec.rows())
+            .rev()
+            .find(|&row| !self.row_is_empty(row))
+            .map(|row| row + 1)
+            .unwrap_or(0);
+        start..self.codec.rows()
+    }
+
+    pub fn isolated_seats(&self) -> Vec<u32> {
+        self.gaps()
+            .into_iter()
+            .filter(|gap| {
+                gap.len() == 1 && gap.start > 0 && (gap.end as usize) < self.occupied.len()
+            })
+            .map(|gap| gap.start)
+            .collect()
+    }
+}
 
-        Seat { row, column }
+impl fmt::Display for SeatMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = (self.codec.rows() - 1).to_string().len();
+        for (row, seats) in self
+            .occupied
+            .chunks(self.codec.columns() as usize)
+            .enumerate()
+        {
+            write!(f, "{:>width$} ", row, width = width)?;
+            for &occupied in seats {
+                f.write_str(if occupied { "#" } else { "." })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
 #[aoc_generator(day5)]
 pub fn parser(input: &str) -> Vec<Seat> {
-    input.lines().map(|l| l.as_bytes().into()).collect()
+    let codec = BoardingPassCodec::default();
+    input.lines().map(|l| codec.decode(l).unwrap()).collect()
 }
 
 #[aoc(day5, part1)]
 pub fn solve_part1(input: &[Seat]) -> u32 {
-    input.iter().map(Seat::seat_id).max().unwrap()
+    let codec = BoardingPassCodec::default();
+    input.iter().map(|seat| codec.seat_id(seat)).max().unwrap()
 }
 
 #[aoc(day5, part2)]
 pub fn solve_part2(input: &[Seat]) -> u32 {
-    let filled_seats: HashSet<_> = input.iter().map(Seat::seat_id).collect();
-
-    // 935 was the maximum seat id from part 1
-    for seat_id in 1..934 {
-        if !filled_seats.contains(&seat_id)
-            && filled_seats.contains(&(seat_id - 1))
-            && filled_seats.contains(&(seat_id + 1))
-        {
-            return seat_id;
-        }
+    let seats = SeatMap::new(BoardingPassCodec::default(), input).unwrap();
+    match seats.isolated_seats().as_slice() {
+        [seat_id] => *seat_id,
+        [] => panic!("No empty seat found!"),
+        _ => panic!("More than one empty seat found!"),
     }
-
-    panic!("No empty seat found!");
 }
@@ -1,33 +1,159 @@
-use aoc_runner_derive::aoc;
-use std::collections::{HashMap, HashSet};
+use aoc_runner_derive::{aoc, aoc_generator};
+use std::fmt;
+use std::iter::FromIterator;
 
-#[aoc(day6, part1)]
-pub fn solve_part1(input: &str) -> usize {
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Answers(u32);
+
+impl Answers {
+    const ALL: Answers = Answers((1 << 26) - 1);
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, question: char) -> bool {
+        question_bit(question)
+            .map(|bit| self.0 & bit != 0)
+            .unwrap_or(false)
+    }
+
+    pub fn union(self, other: Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = char> + '_ {
+        (b'a'..=b'z')
+            .map(char::from)
+            .filter(move |&question| self.contains(question))
+    }
+}
+
+fn question_bit(question: char) -> Option<u32> {
+    if question.is_ascii_lowercase() {
+        Some(1 << (question as u8 - b'a'))
+    } else {
+        None
+    }
+}
+
+impl FromIterator<char> for Answers {
+    fn from_iter<I: IntoIterator<Item = char>>(questions: I) -> Self {
+        Answers(
+            questions
+                .into_iter()
+                .filter_map(question_bit)
+                .fold(0, |a, b| a | b),
+        )
+    }
+}
+
+impl fmt::Display for Answers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.iter()
+            .try_for_each(|question| write!(f, "{}", question))
+    }
+}
+
+pub struct Group {
+    people: Vec<Answers>,
+}
+
+impl Group {
+    pub fn people(&self) -> &[Answers] {
+        &self.people
+    }
+
+    pub fn union(&self) -> Answers {
+        self.people
+            .iter()
+            .fold(Answers::default(), |acc, &person| acc.union(person))
+    }
+
+    pub fn intersection(&self) -> Answers {
+        if self.people.is_empty() {
+            return Answers::default();
+        }
+
+        self.people
+            .iter()
+            .fold(Answers::ALL, |acc, &person| acc.intersection(person))
+    }
+
+    pub fn at_least(&self, count: usize) -> Answers {
+        let histogram = self.histogram();
+        (b'a'..=b'z')
+            .map(char::from)
+            .zip(histogram.iter())
+            .filter(|(_, &answered)| answered >= count && answered > 0)
+            .map(|(question, _)| question)
+            .collect()
+    }
+
+    pub fn histogram(&self) -> [usize; 26] {
+        let mut histogram = [0; 26];
+        for person in &self.people {
+            for (index, count) in histogram.iter_mut().enumerate() {
+                *count += (person.0 >> index & 1) as usize;
+            }
+        }
+        histogram
+    }
+
+    pub fn most_common(&self) -> Answers {
+        self.extreme(|histogram| histogram.iter().copied().max())
+    }
+
+    pub fn least_common(&self) -> Answers {
+        self.extreme(|histogram| histogram.iter().copied().filter(|&c| c > 0).min())
+    }
+
+    fn extreme<F>(&self, pick: F) -> Answers
+    where
+        F: Fn(&[usize; 26]) -> Option<usize>,
+    {
+        let histogram = self.histogram();
+        match pick(&histogram).filter(|&count| count > 0) {
+            Some(target) => (b'a'..=b'z')
+                .map(char::from)
+                .zip(histogram.iter())
+                .filter(|(_, &count)| count == target)
+                .map(|(question, _)| question)
+                .collect(),
+            None => Answers::default(),
+        }
+    }
+}
+
+#[aoc_generator(day6)]
+pub fn parser(input: &str) -> Vec<Group> {
     input
         .split("\n\n")
-        .map(|group| {
-            let set: HashSet<_> = group.chars().filter(|c| c.is_alphabetic()).collect();
-            set.len()
+        .map(|group| Group {
+            people: group
+                .lines()
+                .filter(|person| !person.is_empty())
+                .map(|person| person.chars().collect())
+                .collect(),
         })
-        .sum()
+        .filter(|group| !group.people.is_empty())
+        .collect()
+}
+
+#[aoc(day6, part1)]
+pub fn solve_part1(input: &[Group]) -> usize {
+    input.iter().map(|group| group.union().len()).sum()
 }
 
 #[aoc(day6, part2)]
-pub fn solve_part2(input: &str) -> usize {
-    input
-        .split("\n\n")
-        .map(|group| {
-            let (count, answers) =
-                group
-                    .lines()
-                    .fold((0, HashMap::new()), |(count, mut answers), person| {
-                        for question in person.chars() {
-                            *answers.entry(question).or_insert(0) += 1;
-                        }
-                        (count + 1, answers)
-                    });
-
-            answers.values().filter(|c| **c == count).count()
-        })
-        .sum()
+pub fn solve_part2(input: &[Group]) -> usize {
+    input.iter().map(|group| group.intersection().len()).sum()
 }
@@ -2,83 +2,334 @@ use aoc_runner_derive::aoc;
 use nom::branch::alt;
 use nom::bytes::complete::tag;
 use nom::character::complete::{alpha1, char, digit1, line_ending};
-use nom::combinator::{all_consuming, eof, map, recognize};
-use nom::multi::{fold_many1, many1, separated_list1};
+use nom::combinator::{eof, map, recognize};
+use nom::multi::{many1, separated_list1};
 use nom::sequence::{delimited, separated_pair, terminated, tuple};
 use nom::IResult;
-use std::collections::{HashMap, HashSet, VecDeque};
-
-type Contained<'a> = (u32, &'a str);
-type Bag<'a> = (&'a str, Vec<Contained<'a>>);
-
-fn parse_part1(input: &str) -> HashMap<&str, HashSet<&str>> {
-    all_consuming(fold_many1(
-        bag,
-        HashMap::new(),
-        |mut map, (container, contained)| {
-            for (_, name) in contained {
-                map.entry(name)
-                    .or_insert_with(HashSet::new)
-                    .insert(container);
-            }
-            map
-        },
-    ))(input)
-    .unwrap()
-    .1
-}
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::once;
 
-pub fn parse_part2(input: &str) -> HashMap<&str, Vec<Contained<'_>>> {
-    all_consuming(many1(bag))(input)
-        .unwrap()
-        .1
-        .into_iter()
-        .collect()
+pub type Contained<'a> = (u32, &'a str);
+pub type Bag<'a> = (&'a str, Vec<Contained<'a>>);
+
+#[derive(Debug, PartialEq)]
+pub enum RuleError {
+    Parse { line: usize },
+    Duplicate(String),
+    Dangling { container: String, missing: String },
+    Cycle(Vec<String>),
 }
 
-#[aoc(day7, part1)]
-pub fn solve_part1(input: &str) -> usize {
-    let mut containers = parse_part1(input);
-    let mut results = HashSet::new();
-    let mut queue = VecDeque::new();
-    queue.push_back("shiny gold");
-
-    while let Some(bag) = queue.pop_front() {
-        if let Some(outer_bags) = containers.remove(&bag) {
-            for outer in outer_bags {
-                results.insert(outer);
-                queue.push_back(outer);
-            }
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Parse { line } => write!(f, "could not parse rule on line {}", line),
+            RuleError::Duplicate(color) => write!(f, "{} bags are defined more than once", color),
+            RuleError::Dangling { container, missing } => write!(
+                f,
+                "{} bags contain {} bags, which have no rule",
+                container, missing
+            ),
+            RuleError::Cycle(path) => write!(f, "bags contain themselves: {}", path.join(" -> ")),
         }
     }
+}
+
+impl std::error::Error for RuleError {}
 
-    results.len()
+pub fn parse_rules(input: &str) -> Result<Vec<Bag<'_>>, RuleError> {
+    let rest = match many1(bag)(input) {
+        Ok((rest, rules)) if rest.trim().is_empty() => return Ok(rules),
+        Ok((rest, _)) => rest,
+        Err(_) => input,
+    };
+
+    let line = input[..input.len() - rest.len()].matches('\n').count() + 1;
+    Err(RuleError::Parse { line })
 }
 
-#[aoc(day7, part2)]
-pub fn solve_part2(input: &str) -> u32 {
-    let containers = parse_part2(input);
-    let mut cache = HashMap::new();
-    bags_in("shiny gold", &containers, &mut cache) - 1
+pub struct BagGraph<'a> {
+    contents: BTreeMap<&'a str, Vec<Contained<'a>>>,
+    containers: BTreeMap<&'a str, Vec<&'a str>>,
 }
 
-fn bags_in(
-    name: &str,
-    containers: &HashMap<&str, Vec<Contained<'_>>>,
-    cache: &mut HashMap<String, u32>,
-) -> u32 {
-    if let Some(value) = cache.get(name) {
-        return *value;
+impl<'a> BagGraph<'a> {
+    pub fn new<I: IntoIterator<Item = Bag<'a>>>(rules: I) -> Result<Self, RuleError> {
+        let mut contents = BTreeMap::new();
+        let mut containers = BTreeMap::new();
+
+        for (container, contained) in rules {
+            for &(_, inner) in &contained {
+                containers
+                    .entry(inner)
+                    .or_insert_with(Vec::new)
+                    .push(container);
+            }
+            if contents.insert(container, contained).is_some() {
+                return Err(RuleError::Duplicate(container.into()));
+            }
+        }
+
+        let graph = BagGraph {
+            contents,
+            containers,
+        };
+        graph.validate()?;
+
+        Ok(graph)
+    }
+
+    pub fn parse(input: &'a str) -> Result<Self, RuleError> {
+        BagGraph::new(parse_rules(input)?)
+    }
+
+    fn validate(&self) -> Result<(), RuleError> {
+        for (&container, contained) in &self.contents {
+            if let Some(&(_, missing)) = contained
+                .iter()
+                .find(|(_, inner)| !self.contents.contains_key(inner))
+            {
+                return Err(RuleError::Dangling {
+                    container: container.into(),
+                    missing: missing.into(),
+                });
+            }
+        }
+
+        match self.find_cycle() {
+            Some(cycle) => Err(RuleError::Cycle(
+                cycle.into_iter().map(String::from).collect(),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn find_cycle(&self) -> Option<Vec<&'a str>> {
+        // Each visited bag is either on the current path (false) or fully explored (true)
+        let mut finished: HashMap<&str, bool> = HashMap::new();
+
+        for &root in self.contents.keys() {
+            if finished.contains_key(root) {
+                continue;
+            }
+
+            let mut path = vec![(root, 0)];
+            finished.insert(root, false);
+
+            while let Some(&(bag, index)) = path.last() {
+                match self.contents(bag).get(index) {
+                    Some(&(_, inner)) => {
+                        path.last_mut().unwrap().1 += 1;
+                        match finished.get(inner) {
+                            Some(false) => {
+                                let start = path.iter().position(|&(b, _)| b == inner).unwrap();
+                                let mut cycle: Vec<_> =
+                                    path[start..].iter().map(|&(b, _)| b).collect();
+                                cycle.push(inner);
+                                return Some(cycle);
+                            }
+                            Some(true) => {}
+                            None => {
+                                finished.insert(inner, false);
+                                path.push((inner, 0));
+                            }
+                        }
+                    }
+                    None => {
+                        finished.insert(bag, true);
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn colors(&self) -> impl Iterator<Item = &'a str> + '_ {
+        let mut colors: BTreeSet<_> = self.contents.keys().copied().collect();
+        colors.extend(self.containers.keys().copied());
+        colors.into_iter()
+    }
+
+    pub fn contents(&self, color: &str) -> &[Contained<'a>] {
+        self.contents.get(color).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn containers(&self, color: &str) -> &[&'a str] {
+        self.containers.get(color).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn ancestors(&self, color: &str) -> HashSet<&'a str> {
+        let mut results = HashSet::new();
+        let mut queue: VecDeque<_> = self.containers(color).iter().copied().collect();
+
+        while let Some(bag) = queue.pop_front() {
+            if results.insert(bag) {
+                queue.extend(self.containers(bag));
+            }
+        }
+
+        results
+    }
+
+    pub fn descendants(&self, color: &str) -> HashMap<&'a str, u64> {
+        let mut counts = HashMap::new();
+        let order = self.reachable_order(color);
+        let mut multiples: HashMap<&str, u64> = HashMap::new();
+        multiples.insert(color, 1);
+
+        for bag in order {
+            let multiple = multiples.get(bag).copied().unwrap_or(0);
+            for &(count, inner) in self.contents(bag) {
+                let added = multiple * count as u64;
+                *multiples.entry(inner).or_insert(0) += added;
+                *counts.entry(inner).or_insert(0) += added;
+            }
+        }
+
+        counts
+    }
+
+    pub fn total_contained(&self, color: &str) -> u64 {
+        self.descendants(color).values().sum()
     }
 
-    let mut total = 1;
-    for (count, inner) in containers.get(name).unwrap() {
-        total += *count * bags_in(*inner, &containers, cache);
+    pub fn shortest_chain(&self, outer: &str, inner: &str) -> Option<Vec<&'a str>> {
+        let (&start, _) = self.contents.get_key_value(outer)?;
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+
+        while let Some(bag) = queue.pop_front() {
+            if bag == inner {
+                let mut chain = vec![bag];
+                while let Some(&prev) = previous.get(chain.last().unwrap()) {
+                    chain.push(prev);
+                }
+                chain.reverse();
+                return Some(chain);
+            }
+
+            for &(_, next) in self.contents(bag) {
+                if next != start && !previous.contains_key(next) {
+                    previous.insert(next, bag);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        None
     }
 
-    cache.insert(name.into(), total);
+    pub fn topological_order(&self) -> Vec<&'a str> {
+        let mut incoming: BTreeMap<&str, usize> = self
+            .colors()
+            .map(|color| (color, self.containers(color).len()))
+            .collect();
+        let mut ready: VecDeque<_> = incoming
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&color, _)| color)
+            .collect();
+        let mut order = Vec::with_capacity(incoming.len());
+
+        while let Some(bag) = ready.pop_front() {
+            order.push(bag);
+            for &(_, inner) in self.contents(bag) {
+                let count = incoming.get_mut(inner).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(inner);
+                }
+            }
+        }
+
+        order
+    }
 
-    total
+    pub fn write_dot<W: Write>(&self, mut writer: W, focus: Focus<'_>) -> io::Result<()> {
+        let included: Option<HashSet<&str>> = match focus {
+            Focus::All => None,
+            Focus::From(color) => Some(
+                self.descendants(color)
+                    .into_keys()
+                    .chain(once(color))
+                    .collect(),
+            ),
+            Focus::To(color) => Some(
+                self.ancestors(color)
+                    .into_iter()
+                    .chain(once(color))
+                    .collect(),
+            ),
+        };
+        let include = |color: &str| match &included {
+            Some(set) => set.contains(color),
+            None => true,
+        };
+
+        writeln!(writer, "digraph bags {{")?;
+        for (&container, contained) in self.contents.iter().filter(|(c, _)| include(c)) {
+            writeln!(writer, "    \"{}\";", container)?;
+            for &(count, inner) in contained {
+                if include(inner) {
+                    writeln!(
+                        writer,
+                        "    \"{}\" -> \"{}\" [label=\"{}\", weight={}];",
+                        container, inner, count, count
+                    )?;
+                }
+            }
+        }
+        writeln!(writer, "}}")
+    }
+
+    fn reachable_order<'b>(&'b self, color: &'b str) -> Vec<&'b str> {
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        let mut stack = vec![(color, false)];
+
+        while let Some((bag, expanded)) = stack.pop() {
+            if expanded {
+                order.push(bag);
+            } else if visited.insert(bag) {
+                stack.push((bag, true));
+                for &(_, inner) in self.contents(bag) {
+                    if !visited.contains(inner) {
+                        stack.push((inner, false));
+                    }
+                }
+            }
+        }
+
+        order.reverse();
+        order
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Focus<'a> {
+    All,
+    From(&'a str),
+    To(&'a str),
+}
+
+#[aoc(day7, part1)]
+pub fn solve_part1(input: &str) -> usize {
+    BagGraph::parse(input)
+        .unwrap()
+        .ancestors("shiny gold")
+        .len()
+}
+
+#[aoc(day7, part2)]
+pub fn solve_part2(input: &str) -> u64 {
+    BagGraph::parse(input)
+        .unwrap()
+        .total_contained("shiny gold")
 }
 
 fn bag_name(input: &str) -> IResult<&str, &str> {
@@ -1,7 +1,12 @@
-use aoc_runner_derive::aoc;
-use std::collections::HashSet;
+use aoc_runner_derive::{aoc, aoc_generator};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::str::FromStr;
 
-enum Operation {
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operation {
     Acc(i32),
     Jmp(i32),
     Nop(i32),
@@ -19,107 +24,619 @@ impl Operation {
             _ => unreachable!(),
         }
     }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Operation::Acc(_) => "acc",
+            Operation::Jmp(_) => "jmp",
+            Operation::Nop(_) => "nop",
+        }
+    }
+
+    pub fn argument(&self) -> i32 {
+        match *self {
+            Operation::Acc(value) | Operation::Jmp(value) | Operation::Nop(value) => value,
+        }
+    }
+
+    fn execute(&self, cursor: isize, accumulator: &mut i32) -> isize {
+        match *self {
+            Operation::Acc(value) => {
+                *accumulator += value;
+                cursor + 1
+            }
+            Operation::Jmp(jump) => cursor + jump as isize,
+            Operation::Nop(_) => cursor + 1,
+        }
+    }
 }
 
-enum ExitMode {
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:+}", self.mnemonic(), self.argument())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    Malformed(String),
+    UnknownOpcode(String),
+    InvalidArgument(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed(line) => write!(f, "malformed instruction '{}'", line),
+            ParseError::UnknownOpcode(op) => write!(f, "unknown opcode '{}'", op),
+            ParseError::InvalidArgument(arg) => write!(f, "invalid argument '{}'", arg),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Operation {
+    type Err = ParseError;
+
+    fn from_str(op: &str) -> Result<Self, Self::Err> {
+        let (opcode, argument) = op
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| ParseError::Malformed(op.into()))?;
+        let value = argument
+            .trim()
+            .parse()
+            .map_err(|_| ParseError::InvalidArgument(argument.into()))?;
+
+        match opcode {
+            "acc" => Ok(Operation::Acc(value)),
+            "jmp" => Ok(Operation::Jmp(value)),
+            "nop" => Ok(Operation::Nop(value)),
+            _ => Err(ParseError::UnknownOpcode(opcode.into())),
+        }
+    }
+}
+
+pub fn parse_program(input: &str) -> Result<Vec<Operation>, (usize, ParseError)> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| line.parse().map_err(|err| (index + 1, err)))
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExitMode {
     InfiniteLoop,
     Complete,
+    OutOfBounds(isize),
+    Breakpoint(usize),
 }
 
-impl<'a> From<&'a str> for Operation {
-    fn from(op: &'a str) -> Self {
-        let value = op[4..].parse().unwrap();
-        match &op[0..3] {
-            "acc" => Operation::Acc(value),
-            "jmp" => Operation::Jmp(value),
-            "nop" => Operation::Nop(value),
-            _ => unreachable!(),
-        }
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub cursor: usize,
+    pub operation: Operation,
+    pub accumulator: i32,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}: {:<10} acc={}",
+            self.cursor,
+            self.operation.to_string(),
+            self.accumulator
+        )
     }
 }
 
 pub struct Computer {
     ops: Vec<Operation>,
-    cursor: usize,
+    cursor: isize,
     accumulator: i32,
+    seen: HashSet<usize>,
+    breakpoints: BTreeSet<usize>,
+    tracing: bool,
+    trace: Vec<TraceEntry>,
 }
 
 impl Computer {
-    fn new(ops: Vec<Operation>) -> Self {
+    pub fn new(ops: Vec<Operation>) -> Self {
         Self {
             ops,
             cursor: 0,
             accumulator: 0,
+            seen: HashSet::new(),
+            breakpoints: BTreeSet::new(),
+            tracing: false,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn program(&self) -> &[Operation] {
+        &self.ops
+    }
+
+    pub fn cursor(&self) -> isize {
+        self.cursor
+    }
+
+    pub fn accumulator(&self) -> i32 {
+        self.accumulator
+    }
+
+    pub fn set_tracing(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
+
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    pub fn add_breakpoint(&mut self, position: usize) {
+        self.breakpoints.insert(position);
+    }
+
+    pub fn remove_breakpoint(&mut self, position: usize) -> bool {
+        self.breakpoints.remove(&position)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    fn exit_mode(&self) -> Option<ExitMode> {
+        if self.cursor < 0 || self.cursor > self.ops.len() as isize {
+            Some(ExitMode::OutOfBounds(self.cursor))
+        } else if self.cursor == self.ops.len() as isize {
+            Some(ExitMode::Complete)
+        } else {
+            None
         }
     }
 
-    fn run(&mut self) -> ExitMode {
-        let mut seen = HashSet::new();
+    pub fn step(&mut self) -> Option<ExitMode> {
+        if let Some(mode) = self.exit_mode() {
+            return Some(mode);
+        }
+
+        let position = self.cursor as usize;
+        let operation = self.ops[position];
+        self.seen.insert(position);
+        self.cursor = operation.execute(self.cursor, &mut self.accumulator);
+
+        if self.tracing {
+            self.trace.push(TraceEntry {
+                cursor: position,
+                operation,
+                accumulator: self.accumulator,
+            });
+        }
+
+        self.exit_mode()
+    }
+
+    pub fn run(&mut self) -> ExitMode {
+        let start = self.cursor;
 
         loop {
-            if self.cursor >= self.ops.len() {
-                break ExitMode::Complete;
+            if let Some(mode) = self.exit_mode() {
+                break mode;
             }
-            if seen.contains(&self.cursor) {
+
+            let position = self.cursor as usize;
+            if self.seen.contains(&position) {
                 break ExitMode::InfiniteLoop;
-            } else {
-                seen.insert(self.cursor);
+            }
+            if self.cursor != start && self.breakpoints.contains(&position) {
+                break ExitMode::Breakpoint(position);
             }
 
-            match self.ops[self.cursor] {
-                Operation::Acc(value) => {
-                    self.accumulator += value;
-                    self.cursor += 1;
+            self.step();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+        self.accumulator = 0;
+        self.seen.clear();
+        self.trace.clear();
+    }
+}
+
+const DEBUGGER_HELP: &str = "\
+commands:
+  step [n]       execute n instructions (default 1)
+  continue       run until a breakpoint, a loop or the end of the program
+  break <n>      set a breakpoint on instruction n
+  delete <n>     remove the breakpoint on instruction n
+  list           show the program around the cursor
+  state          show the cursor and accumulator
+  trace [on|off] show the execution trace, or toggle tracing
+  reset          restart the program
+  quit           leave the debugger
+";
+
+pub fn debug<R: BufRead, W: Write>(
+    computer: &mut Computer,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    write!(output, "(dbg) ")?;
+    output.flush()?;
+
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        let argument = words.clone().nth(1);
+
+        match words.next() {
+            Some("step") | Some("s") => {
+                let count = argument.and_then(|n| n.parse().ok()).unwrap_or(1);
+                for _ in 0..count {
+                    if let Some(mode) = computer.step() {
+                        writeln!(output, "stopped: {:?}", mode)?;
+                        break;
+                    }
                 }
-                Operation::Jmp(jump) => {
-                    self.cursor = ((self.cursor as i32) + jump) as usize;
+                print_state(computer, &mut output)?;
+            }
+            Some("continue") | Some("c") => {
+                let mode = computer.run();
+                writeln!(output, "stopped: {:?}", mode)?;
+                print_state(computer, &mut output)?;
+            }
+            Some("break") | Some("b") => match argument.and_then(|n| n.parse().ok()) {
+                Some(position) => computer.add_breakpoint(position),
+                None => writeln!(output, "usage: break <instruction>")?,
+            },
+            Some("delete") | Some("d") => match argument.and_then(|n| n.parse().ok()) {
+                Some(position) => {
+                    if !computer.remove_breakpoint(position) {
+                        writeln!(output, "no breakpoint on instruction {}", position)?;
+                    }
                 }
-                Operation::Nop(_) => {
-                    self.cursor += 1;
+                None => writeln!(output, "usage: delete <instruction>")?,
+            },
+            Some("list") | Some("l") => {
+                let cursor = computer.cursor();
+                let first = (cursor - 5).max(0) as usize;
+                for (index, op) in computer.program().iter().enumerate().skip(first).take(11) {
+                    let marker = if index as isize == cursor { "=>" } else { "  " };
+                    let stop = if computer.breakpoints().any(|b| b == index) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    writeln!(output, "{}{}{:>5}: {}", marker, stop, index, op)?;
                 }
             }
+            Some("state") | Some("p") => print_state(computer, &mut output)?,
+            Some("trace") | Some("t") => match argument {
+                Some("on") => computer.set_tracing(true),
+                Some("off") => computer.set_tracing(false),
+                _ => {
+                    for entry in computer.trace() {
+                        writeln!(output, "{}", entry)?;
+                    }
+                }
+            },
+            Some("reset") => computer.reset(),
+            Some("quit") | Some("q") => return Ok(()),
+            Some("help") | Some("h") => write!(output, "{}", DEBUGGER_HELP)?,
+            Some(other) => writeln!(output, "unknown command '{}', try help", other)?,
+            None => {}
         }
+
+        write!(output, "(dbg) ")?;
+        output.flush()?;
     }
 
-    fn reset(&mut self) {
-        self.cursor = 0;
-        self.accumulator = 0;
+    writeln!(output)
+}
+
+fn print_state<W: Write>(computer: &Computer, output: &mut W) -> io::Result<()> {
+    let cursor = computer.cursor();
+    match computer.program().get(cursor as usize) {
+        Some(op) if cursor >= 0 => writeln!(
+            output,
+            "cursor={} ({}) acc={}",
+            cursor,
+            op,
+            computer.accumulator()
+        ),
+        _ => writeln!(output, "cursor={} acc={}", cursor, computer.accumulator()),
     }
 }
 
-#[aoc(day8, part1)]
-pub fn solve_part1(input: &str) -> i32 {
-    let mut computer = Computer::new(input.lines().map(Operation::from).collect());
-    match computer.run() {
-        ExitMode::InfiniteLoop => computer.accumulator,
-        _ => unreachable!(),
+#[derive(Debug, PartialEq)]
+pub enum Repair {
+    AlreadyTerminates,
+    Fixed { position: usize, accumulator: i32 },
+    Impossible,
+    Ambiguous(Vec<usize>),
+}
+
+fn successor(ops: &[Operation], position: usize) -> Option<usize> {
+    let next = ops[position].execute(position as isize, &mut 0);
+    if next >= 0 && next <= ops.len() as isize {
+        Some(next as usize)
+    } else {
+        None
     }
 }
 
-#[aoc(day8, part2)]
-pub fn solve_part2(input: &str) -> i32 {
-    let ops: Vec<_> = input.lines().map(Operation::from).collect();
-    let ops_to_toggle: Vec<_> = ops
-        .iter()
-        .enumerate()
-        .filter_map(|(index, op)| match op {
-            Operation::Jmp(_) | Operation::Nop(_) => Some(index),
-            _ => None,
-        })
-        .collect();
-    let mut computer = Computer::new(ops);
+fn terminating(ops: &[Operation]) -> Vec<bool> {
+    let mut predecessors = vec![Vec::new(); ops.len() + 1];
+    for position in 0..ops.len() {
+        if let Some(next) = successor(ops, position) {
+            predecessors[next].push(position);
+        }
+    }
+
+    let mut reaches_end = vec![false; ops.len() + 1];
+    let mut stack = vec![ops.len()];
+    reaches_end[ops.len()] = true;
+
+    while let Some(position) = stack.pop() {
+        for &previous in &predecessors[position] {
+            if !reaches_end[previous] {
+                reaches_end[previous] = true;
+                stack.push(previous);
+            }
+        }
+    }
+
+    reaches_end
+}
+
+pub fn repair(ops: &[Operation]) -> Repair {
+    let reaches_end = terminating(ops);
+    if reaches_end[0] {
+        return Repair::AlreadyTerminates;
+    }
+
+    let mut flipped = ops.to_vec();
+    let mut candidates = Vec::new();
+    let mut visited = vec![false; ops.len()];
+    let mut position = Some(0);
+
+    while let Some(current) = position.filter(|&p| p < ops.len() && !visited[p]) {
+        visited[current] = true;
+        if let Operation::Jmp(_) | Operation::Nop(_) = ops[current] {
+            flipped[current].toggle();
+            if matches!(successor(&flipped, current), Some(next) if reaches_end[next]) {
+                candidates.push(current);
+            }
+            flipped[current].toggle();
+        }
+        position = successor(ops, current);
+    }
+
+    match candidates.as_slice() {
+        [] => Repair::Impossible,
+        &[position] => {
+            flipped[position].toggle();
+            let mut computer = Computer::new(flipped);
+            computer.run();
+            Repair::Fixed {
+                position,
+                accumulator: computer.accumulator,
+            }
+        }
+        _ => Repair::Ambiguous(candidates),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    Block(usize),
+    Exit,
+    OutOfBounds(isize),
+}
+
+#[derive(Debug)]
+pub struct BasicBlock {
+    pub instructions: Range<usize>,
+    pub successor: Target,
+}
+
+pub struct ControlFlowGraph {
+    blocks: Vec<BasicBlock>,
+    reachable: Vec<bool>,
+}
+
+impl ControlFlowGraph {
+    pub fn new(ops: &[Operation]) -> Self {
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (position, op) in ops.iter().enumerate() {
+            if let Operation::Jmp(_) = op {
+                leaders.insert(position + 1);
+                if let Some(target) = successor(ops, position) {
+                    leaders.insert(target);
+                }
+            }
+        }
+        leaders.retain(|&leader| leader < ops.len());
+
+        let starts: Vec<_> = leaders.into_iter().collect();
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| {
+                let end = starts.get(index + 1).copied().unwrap_or(ops.len());
+                let last = end - 1;
+                let next = ops[last].execute(last as isize, &mut 0);
+                let successor = if next == ops.len() as isize {
+                    Target::Exit
+                } else if next < 0 || next > ops.len() as isize {
+                    Target::OutOfBounds(next)
+                } else {
+                    Target::Block(starts.binary_search(&(next as usize)).unwrap())
+                };
+
+                BasicBlock {
+                    instructions: start..end,
+                    successor,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut reachable = vec![false; blocks.len()];
+        let mut current = if blocks.is_empty() { None } else { Some(0) };
+        while let Some(index) = current.filter(|&index| !reachable[index]) {
+            reachable[index] = true;
+            current = match blocks[index].successor {
+                Target::Block(next) => Some(next),
+                _ => None,
+            };
+        }
+
+        ControlFlowGraph { blocks, reachable }
+    }
 
-    for toggled in ops_to_toggle {
-        computer.ops[toggled].toggle();
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.reachable[block]
+    }
+
+    pub fn unreachable(&self) -> Vec<Range<usize>> {
+        self.blocks
+            .iter()
+            .zip(&self.reachable)
+            .filter(|(_, &reachable)| !reachable)
+            .map(|(block, _)| block.instructions.clone())
+            .collect()
+    }
+
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        // Every block has exactly one successor, so each loop is a simple cycle
+        let mut finished = vec![false; self.blocks.len()];
+        let mut loops = Vec::new();
+
+        for start in 0..self.blocks.len() {
+            let mut path = Vec::new();
+            let mut current = Some(start);
+
+            while let Some(index) = current.filter(|&index| !finished[index]) {
+                if let Some(offset) = path.iter().position(|&seen| seen == index) {
+                    loops.push(path[offset..].to_vec());
+                    break;
+                }
+                path.push(index);
+                current = match self.blocks[index].successor {
+                    Target::Block(next) => Some(next),
+                    _ => None,
+                };
+            }
+
+            for index in path {
+                finished[index] = true;
+            }
+        }
+
+        loops
+    }
+
+    pub fn write_dot<W: Write>(&self, ops: &[Operation], mut writer: W) -> io::Result<()> {
+        writeln!(writer, "digraph program {{")?;
+        writeln!(writer, "    node [shape=box, fontname=monospace];")?;
 
-        match computer.run() {
-            ExitMode::Complete => return computer.accumulator,
-            ExitMode::InfiniteLoop => {
-                computer.ops[toggled].toggle();
-                computer.reset();
+        for (index, block) in self.blocks.iter().enumerate() {
+            let label: String = block
+                .instructions
+                .clone()
+                .map(|position| format!("{}: {}\\l", position, ops[position]))
+                .collect();
+            let style = if self.reachable[index] {
+                ""
+            } else {
+                ", style=dashed"
+            };
+            writeln!(writer, "    b{} [label=\"{}\"{}];", index, label, style)?;
+
+            match block.successor {
+                Target::Block(next) => writeln!(writer, "    b{} -> b{};", index, next)?,
+                Target::Exit => writeln!(writer, "    b{} -> exit;", index)?,
+                Target::OutOfBounds(position) => writeln!(
+                    writer,
+                    "    b{} -> oob{};\n    oob{} [label=\"out of bounds: {}\", shape=octagon];",
+                    index, index, index, position
+                )?,
             }
         }
+
+        writeln!(writer, "    exit [shape=doublecircle];")?;
+        writeln!(writer, "}}")
     }
+}
 
-    unreachable!();
+pub fn disassemble<W: Write>(ops: &[Operation], mut writer: W) -> io::Result<()> {
+    let graph = ControlFlowGraph::new(ops);
+    let loops = graph.loops();
+
+    for (index, block) in graph.blocks().iter().enumerate() {
+        write!(
+            writer,
+            "block {} ({}..{})",
+            index, block.instructions.start, block.instructions.end
+        )?;
+        if !graph.is_reachable(index) {
+            write!(writer, " unreachable")?;
+        }
+        if let Some(number) = loops.iter().position(|l| l.contains(&index)) {
+            write!(writer, " loop {}", number)?;
+        }
+        writeln!(writer)?;
+
+        for position in block.instructions.clone() {
+            let op = ops[position].to_string();
+            match (ops[position], block.successor) {
+                (Operation::Jmp(_), Target::Block(next)) => {
+                    writeln!(writer, "{:>6}: {:<10} ; -> block {}", position, op, next)?
+                }
+                (Operation::Jmp(_), Target::Exit) => {
+                    writeln!(writer, "{:>6}: {:<10} ; -> exit", position, op)?
+                }
+                (Operation::Jmp(_), Target::OutOfBounds(target)) => writeln!(
+                    writer,
+                    "{:>6}: {:<10} ; -> {} (out of bounds)",
+                    position, op, target
+                )?,
+                _ => writeln!(writer, "{:>6}: {}", position, op)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[aoc_generator(day8)]
+pub fn parser(input: &str) -> Vec<Operation> {
+    parse_program(input).unwrap()
+}
+
+#[aoc(day8, part1)]
+pub fn solve_part1(input: &[Operation]) -> i32 {
+    let mut computer = Computer::new(input.to_vec());
+    match computer.run() {
+        ExitMode::InfiniteLoop => computer.accumulator,
+        _ => unreachable!(),
+    }
+}
+
+#[aoc(day8, part2)]
+pub fn solve_part2(input: &[Operation]) -> i32 {
+    match repair(input) {
+        Repair::Fixed { accumulator, .. } => accumulator,
+        other => panic!("Could not repair program: {:?}", other),
+    }
 }
@@ -1,32 +1,48 @@
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::cmp::Ordering;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io::{self, BufRead};
+use std::ops::Range;
 
 struct SlidingWindow {
     size: usize,
     numbers: VecDeque<u64>,
-    cache: HashSet<u64>,
+    sums: HashMap<u64, usize>,
 }
 
 impl SlidingWindow {
     fn new(size: usize) -> Self {
         Self {
             size,
-            numbers: VecDeque::with_capacity(25),
-            cache: HashSet::with_capacity(25),
+            numbers: VecDeque::with_capacity(size),
+            sums: HashMap::with_capacity(size * size / 2),
         }
     }
 
     fn add(&mut self, value: u64) {
+        // An empty window has no sums, so every number checked against it is invalid
+        if self.size == 0 {
+            return;
+        }
+
         if self.is_full() {
             let removed = self.numbers.pop_front().unwrap();
-            self.numbers.push_back(value);
-            self.cache.remove(&removed);
-            self.cache.insert(value);
-        } else {
-            self.numbers.push_back(value);
-            self.cache.insert(value);
+            // Sums that overflow were never stored, as no u64 can match them
+            for sum in self.numbers.iter().filter_map(|&n| removed.checked_add(n)) {
+                let count = self.sums.get_mut(&sum).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    self.sums.remove(&sum);
+                }
+            }
+        }
+
+        // Sums are tracked per pair of positions, so a repeated value can pair with its
+        // other occurrences but never with itself, and dropping one copy keeps the rest
+        for sum in self.numbers.iter().filter_map(|&n| value.checked_add(n)) {
+            *self.sums.entry(sum).or_insert(0) += 1;
         }
+        self.numbers.push_back(value);
     }
 
     fn is_full(&self) -> bool {
@@ -34,13 +50,54 @@ impl SlidingWindow {
     }
 
     fn is_two_sum(&self, value: u64) -> bool {
-        for &number in self.cache.iter() {
-            if number < value && self.cache.contains(&(value - number)) {
-                return true;
-            }
-        }
+        self.sums.contains_key(&value)
+    }
+
+    fn check(&mut self, value: u64) -> bool {
+        let valid = !self.is_full() || self.is_two_sum(value);
+        self.add(value);
+        valid
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Invalid {
+    pub position: usize,
+    pub value: u64,
+}
+
+pub fn invalid_numbers<I>(numbers: I, preamble: usize) -> impl Iterator<Item = Invalid>
+where
+    I: IntoIterator<Item = u64>,
+{
+    let mut window = SlidingWindow::new(preamble);
+    numbers
+        .into_iter()
+        .enumerate()
+        .filter(move |&(_, value)| !window.check(value))
+        .map(|(position, value)| Invalid { position, value })
+}
 
-        false
+pub fn read_invalid_numbers<R: BufRead>(reader: R, preamble: usize) -> io::Result<Vec<Invalid>> {
+    // Reading stops at the first bad line, whose error replaces the partial results
+    let mut error = None;
+    let numbers = reader
+        .lines()
+        .map(|line| line.and_then(|line| parse_line(&line)))
+        .map_while(|result| result.map_err(|err| error = Some(err)).ok())
+        .flatten();
+
+    let invalid = invalid_numbers(numbers, preamble).collect();
+    error.map_or(Ok(invalid), Err)
+}
+
+fn parse_line(line: &str) -> io::Result<Option<u64>> {
+    match line.trim() {
+        "" => Ok(None),
+        value => value
+            .parse()
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
     }
 }
 
@@ -51,76 +108,127 @@ pub fn parser(input: &str) -> Vec<u64> {
 
 #[aoc(day9, part1)]
 pub fn solve_part1(input: &[u64]) -> u64 {
-    let mut window = SlidingWindow::new(25);
-    for &number in input.iter() {
-        if window.is_full() && !window.is_two_sum(number) {
-            return number;
+    invalid_numbers(input.iter().copied(), 25)
+        .next()
+        .unwrap()
+        .value
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContiguousSum {
+    pub range: Range<usize>,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ContiguousSum {
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+}
+
+pub fn find_contiguous_sums(values: &[i64], target: i64, min_len: usize) -> Vec<ContiguousSum> {
+    let min_len = min_len.max(1);
+    let mut prefixes: HashMap<i64, Vec<usize>> = HashMap::new();
+    let mut total = 0;
+    let mut found = Vec::new();
+    prefixes.insert(0, vec![0]);
+
+    for (index, &value) in values.iter().enumerate() {
+        let end = index + 1;
+        total += value;
+
+        if let Some(starts) = prefixes.get(&(total - target)) {
+            for &start in starts.iter().take_while(|&&start| end - start >= min_len) {
+                let slice = &values[start..end];
+                found.push(ContiguousSum {
+                    range: start..end,
+                    min: *slice.iter().min().unwrap(),
+                    max: *slice.iter().max().unwrap(),
+                });
+            }
         }
 
-        window.add(number);
+        prefixes.entry(total).or_default().push(end);
     }
 
-    unreachable!();
+    found.sort_by_key(|sum| (sum.range.start, sum.range.end));
+    found
 }
 
-struct TotalWindow {
-    numbers: VecDeque<u64>,
-    total: u64,
+#[aoc(day9, part2)]
+pub fn solve_part2(input: &[u64]) -> i64 {
+    let target = i64::try_from(solve_part1(input)).unwrap();
+    let values: Vec<_> = input.iter().map(|&n| i64::try_from(n).unwrap()).collect();
+    let weakness = find_contiguous_sums(&values, target, 2)
+        .into_iter()
+        .next()
+        .unwrap();
+
+    weakness.min + weakness.max
 }
 
-impl TotalWindow {
-    fn new() -> Self {
-        Self {
-            numbers: VecDeque::new(),
-            total: 0,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(values: &[u64]) -> SlidingWindow {
+        let mut window = SlidingWindow::new(values.len());
+        for &value in values {
+            window.add(value);
         }
+        window
     }
 
-    fn add(&mut self, value: u64) {
-        self.numbers.push_back(value);
-        self.total += value;
+    #[test]
+    fn evicting_a_duplicate_keeps_the_other_copy() {
+        let mut window = window(&[5, 5, 1]);
+        assert!(window.is_two_sum(10));
+
+        window.add(2);
+        assert!(!window.is_two_sum(10));
+        assert!(window.is_two_sum(6));
+
+        window.add(3);
+        assert!(!window.is_two_sum(6));
+        assert!(window.is_two_sum(5));
     }
 
-    fn bump(&mut self) {
-        let removed = self.numbers.pop_front().unwrap();
-        self.total -= removed;
+    #[test]
+    fn single_value_does_not_pair_with_itself() {
+        let window = window(&[1, 5, 3]);
+        assert!(!window.is_two_sum(10));
+        assert!(!window.is_two_sum(2));
+        assert!(window.is_two_sum(8));
     }
 
-    fn min_max(self) -> (u64, u64) {
-        self.numbers
-            .into_iter()
-            .fold((u64::MAX, u64::MIN), |(min, max), num| {
-                (
-                    if num < min { num } else { min },
-                    if num > max { num } else { max },
-                )
-            })
+    #[test]
+    fn repeated_value_pairs_with_its_copy() {
+        let window = window(&[5, 1, 5]);
+        assert!(window.is_two_sum(10));
+        assert!(window.is_two_sum(6));
     }
-}
 
-#[aoc(day9, part2)]
-pub fn solve_part2(input: &[u64]) -> u64 {
-    let part1_solution = 22477624; // Solution from running Part 1
-    let mut window = TotalWindow::new();
-
-    for &number in input.iter() {
-        window.add(number);
-
-        loop {
-            match window.total.cmp(&part1_solution) {
-                Ordering::Equal => {
-                    let (min, max) = window.min_max();
-                    return min + max;
+    #[test]
+    fn invalid_numbers_with_repeated_values() {
+        let numbers = vec![5, 5, 1, 10, 10, 20, 10];
+        let invalid: Vec<_> = invalid_numbers(numbers, 3).collect();
+        assert_eq!(
+            invalid,
+            vec![
+                Invalid {
+                    position: 4,
+                    value: 10
+                },
+                Invalid {
+                    position: 6,
+                    value: 10
                 }
-                Ordering::Greater => {
-                    window.bump();
-                }
-                Ordering::Less => {
-                    break;
-                }
-            }
-        }
+            ]
+        );
     }
-
-    unreachable!();
 }